@@ -0,0 +1,109 @@
+// Adapter is a handle to a physical graphics card (or software renderer) available in given backend.
+// Each adapter supports different set of optional features and limits so this module helps
+// with figuring out what we can use on the machine we run on.
+
+// Optional features that renderer can make use of, reported at startup.
+pub const OPTIONAL_FEATURES: &[(wgpu::Features, &str)] = &[
+    (wgpu::Features::TEXTURE_BINDING_ARRAY, "TEXTURE_BINDING_ARRAY"),
+    (wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY, "STORAGE_RESOURCE_BINDING_ARRAY"),
+    (wgpu::Features::POLYGON_MODE_LINE, "POLYGON_MODE_LINE"),
+    (wgpu::Features::POLYGON_MODE_POINT, "POLYGON_MODE_POINT"),
+    (wgpu::Features::TIMESTAMP_QUERY, "TIMESTAMP_QUERY"),
+    (wgpu::Features::TEXTURE_COMPRESSION_BC, "TEXTURE_COMPRESSION_BC"),
+];
+
+/**
+    Adapter requested on command line, either by its position in the list printed
+    by `--list-adapters` or by (case insensitive) part of its name.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl std::str::FromStr for AdapterSelector {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse::<usize>() {
+            Ok(index) => AdapterSelector::Index(index),
+            Err(_) => AdapterSelector::Name(s.to_lowercase()),
+        })
+    }
+}
+
+impl std::fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdapterSelector::Index(index) => write!(f, "{}", index),
+            AdapterSelector::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/**
+    Picks adapter that is compatible with surface (if there is any).
+    Without selector wgpu chooses adapter by power preference.
+*/
+pub async fn select_adapter(
+    instance: &wgpu::Instance,
+    backends: wgpu::Backends,
+    selector: Option<&AdapterSelector>,
+    power_preference: wgpu::PowerPreference,
+    surface: Option<&wgpu::Surface>,
+) -> Option<wgpu::Adapter> {
+    let Some(selector) = selector else {
+        return instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface: surface,
+                force_fallback_adapter: false,
+            },
+        ).await;
+    };
+
+    let adapter = instance.enumerate_adapters(backends)
+        .enumerate()
+        .find(|(idx, adapter)| match selector {
+            AdapterSelector::Index(index) => idx == index,
+            AdapterSelector::Name(name) => adapter.get_info().name.to_lowercase().contains(name),
+        })
+        .map(|(_, adapter)| adapter)?;
+
+    if let Some(surface) = surface {
+        if !adapter.is_surface_supported(surface) {
+            log::error!("Adapter {} can't present to the window surface", adapter.get_info().name);
+            return None;
+        }
+    }
+    Some(adapter)
+}
+
+pub fn print_adapters(instance: &wgpu::Instance, backends: wgpu::Backends) {
+    for (idx, adapter) in instance.enumerate_adapters(backends).enumerate() {
+        let info = adapter.get_info();
+        let limits = adapter.limits();
+        println!("{}: {}", idx, info.name);
+        println!("    backend: {:?}, device type: {:?}, driver: {} {}", info.backend, info.device_type, info.driver, info.driver_info);
+        println!("    features: {:?}", adapter.features());
+        println!(
+            "    limits: max texture 2d {}, max array layers {}, max bind groups {}, max sampled textures per stage {}, max samplers per stage {}",
+            limits.max_texture_dimension_2d,
+            limits.max_texture_array_layers,
+            limits.max_bind_groups,
+            limits.max_sampled_textures_per_shader_stage,
+            limits.max_samplers_per_shader_stage,
+        );
+    }
+}
+
+pub fn log_capability_report(adapter: &wgpu::Adapter) {
+    let info = adapter.get_info();
+    log::info!("Using adapter {} ({:?}, {:?})", info.name, info.backend, info.device_type);
+    let features = adapter.features();
+    for (feature, name) in OPTIONAL_FEATURES {
+        let available = if features.contains(*feature) { "available" } else { "not available" };
+        log::info!("    {}: {}", name, available);
+    }
+}
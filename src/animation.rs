@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnimationMode {
+    // 0, 1, 2, 0, 1, 2, ...
+    #[default]
+    Loop,
+    // 0, 1, 2, 1, 0, 1, ...
+    PingPong,
+    // 0, 1, 2, 2, 2, ... the last frame stays.
+    Once,
+}
+
+impl AnimationMode {
+    // Matches switch in `animation_frame` of shader.wgsl.
+    fn to_shader(self) -> i32 {
+        match self {
+            AnimationMode::Loop => 0,
+            AnimationMode::PingPong => 1,
+            AnimationMode::Once => 2,
+        }
+    }
+}
+
+/**
+    Frames of animation are either consecutive texture layers starting at instance `texture_index`,
+    or cells of a sprite sheet starting at the cell selected by instance UV transform.
+    Sheet cells have the size of UV transform scale and are read row by row, `sheet_columns` per row.
+
+    Current frame is computed by main vertex shader from time in camera uniform, so animated
+    instances are not touched by CPU after they are written to instance buffer.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FlipbookAnimation {
+    pub frame_count: u32,
+    pub frames_per_second: f32,
+    #[serde(default)]
+    pub mode: AnimationMode,
+    // Without it frames are texture layers.
+    #[serde(default)]
+    pub sheet_columns: Option<u32>,
+    // Time in seconds when the first frame is shown, different values make instances go out of step.
+    #[serde(default)]
+    pub start_time: f32,
+}
+
+impl FlipbookAnimation {
+    // (frame count, mode, sheet columns), frame count 1 means no animation and 0 columns means texture layers.
+    pub fn to_raw_frames(animation: Option<&Self>) -> [i32; 3] {
+        match animation {
+            Some(animation) => [
+                animation.frame_count as i32,
+                animation.mode.to_shader(),
+                animation.sheet_columns.unwrap_or(0) as i32,
+            ],
+            None => [1, 0, 0],
+        }
+    }
+
+    // (frames per second, start time)
+    pub fn to_raw_timing(animation: Option<&Self>) -> [f32; 2] {
+        animation.map_or([0.0, 0.0], |animation| [animation.frames_per_second, animation.start_time])
+    }
+}
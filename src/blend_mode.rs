@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+/**
+    How colour of an instance is combined with what is already drawn.
+
+    Opaque and alpha tested instances are drawn in main pass and write depth. The rest is
+    transparent, such instances are drawn after the sky in their own pass, sorted back to front
+    by distance from the camera and without writing depth, so they do not hide each other.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum BlendMode {
+    // Alpha of texture is ignored.
+    #[default]
+    Opaque,
+    // Pixels with alpha below cutoff are discarded, the rest is opaque.
+    AlphaTest { cutoff: f32 },
+    // Colour is mixed with the background by alpha.
+    AlphaBlend,
+    // Colour multiplied by alpha is added to the background, for glows and fire.
+    Additive,
+    // Like alpha blend, but texture colour is already multiplied by alpha.
+    Premultiplied,
+}
+
+impl BlendMode {
+    // Blend modes drawn in transparent pass, there is a pipeline for each of them.
+    pub const TRANSPARENT: [BlendMode; 3] = [BlendMode::AlphaBlend, BlendMode::Additive, BlendMode::Premultiplied];
+
+    pub fn is_transparent(self) -> bool {
+        Self::TRANSPARENT.contains(&self)
+    }
+
+    // Main shader discards pixels with alpha below this, 0 keeps all of them.
+    pub fn alpha_cutoff(self) -> f32 {
+        match self {
+            BlendMode::AlphaTest { cutoff } => cutoff,
+            _ => 0.0,
+        }
+    }
+
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Opaque | BlendMode::AlphaTest { .. } => wgpu::BlendState::REPLACE,
+            BlendMode::AlphaBlend => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                // Alpha of the target stays as it was.
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            BlendMode::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use crate::input::Action;
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -26,7 +26,15 @@ impl Camera {
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
         // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    // Like view projection matrix but camera only rotates, it stays in the origin. Used for skybox.
+    pub fn build_rotation_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        use cgmath::EuclideanSpace;
+        let view = cgmath::Matrix4::look_to_rh(cgmath::Point3::origin(), self.target - self.eye, self.up);
+        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
 }
 
@@ -38,6 +46,10 @@ pub struct CameraUniform {
     // We can't use cgmath with bytemuck directly so we'll have
     // to convert the Matrix4 into a 4x4 f32 array
     pub view_proj: [[f32; 4]; 4],
+    // Seconds of fixed updates since start, drives flipbook animations (see animation.rs).
+    pub time: f32,
+    // Uniform structs are aligned to 16 bytes.
+    _padding: [f32; 3],
 }
 
 impl CameraUniform {
@@ -45,6 +57,8 @@ impl CameraUniform {
         use cgmath::SquareMatrix;
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            time: 0.0,
+            _padding: [0.0; 3],
         }
     }
 
@@ -72,36 +86,32 @@ impl CameraController {
         }
     }
 
-    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput {
-                input: KeyboardInput {
-                    state,
-                    virtual_keycode: Some(keycode),
-                    ..
-                },
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {
-                    VirtualKeyCode::W | VirtualKeyCode::Up => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::A | VirtualKeyCode::Left => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::S | VirtualKeyCode::Down => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    VirtualKeyCode::D | VirtualKeyCode::Right => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    // Returns whether the action moves camera.
+    pub fn process_action(&mut self, action: Action, is_pressed: bool) -> bool {
+        match action {
+            Action::MoveForward => {
+                self.is_forward_pressed = is_pressed;
+                true
+            }
+            Action::MoveLeft => {
+                self.is_left_pressed = is_pressed;
+                true
+            }
+            Action::MoveBackward => {
+                self.is_backward_pressed = is_pressed;
+                true
+            }
+            Action::MoveRight => {
+                self.is_right_pressed = is_pressed;
+                true
             }
             _ => false,
         }
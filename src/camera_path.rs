@@ -0,0 +1,378 @@
+use std::path::{Path, PathBuf};
+
+use cgmath::prelude::*;
+use cgmath::{Point3, Quaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::ron_position;
+
+// Time between keyframe added from current view and the last keyframe of the path.
+const KEYFRAME_INTERVAL: f32 = 2.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CameraPathError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{path}:{line}:{column}: {message}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("{path}: keyframe {index} is not later than the previous one")]
+    Unordered {
+        path: PathBuf,
+        index: usize,
+    },
+    #[error("{path}: keyframe {index} has no view direction, its eye and target are the same or not finite")]
+    Degenerate {
+        path: PathBuf,
+        index: usize,
+    },
+    #[error("could not serialize camera path: {0}")]
+    Serialize(String),
+    #[error("could not write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    // Seconds since start of the path.
+    pub time: f32,
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub fovy: f32,
+}
+
+impl Keyframe {
+    fn eye(&self) -> Vector3<f32> {
+        self.eye.into()
+    }
+
+    fn forward(&self) -> Vector3<f32> {
+        Vector3::from(self.target) - self.eye()
+    }
+
+    // Direction of the view can be normalized.
+    fn has_direction(&self) -> bool {
+        let length = self.forward().magnitude();
+        length.is_finite() && length > f32::EPSILON * Vector3::from(self.eye).magnitude().max(1.0)
+    }
+}
+
+/**
+    Camera fly-through. Eye goes through keyframes along Catmull-Rom spline parameterised by
+    keyframe times, view direction is slerped between directions of neighbouring keyframes and
+    distance to target and fovy are interpolated linearly. Looping path goes back from the last keyframe to the first one
+    in the same time as between the first two keyframes.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+    #[serde(default)]
+    pub looping: bool,
+}
+
+impl CameraPath {
+    pub fn load(path: &Path) -> Result<Self, CameraPathError> {
+        let source = std::fs::read_to_string(path).map_err(|source| CameraPathError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&source, path)
+    }
+
+    pub fn parse(source: &str, path: &Path) -> Result<Self, CameraPathError> {
+        let camera_path: CameraPath = ron_position::from_str(source).map_err(|e| CameraPathError::Parse {
+            path: path.to_path_buf(),
+            line: e.line,
+            column: e.column,
+            message: e.message,
+        })?;
+        if let Some(index) = (1..camera_path.keyframes.len())
+            .find(|&i| camera_path.keyframes[i].time <= camera_path.keyframes[i - 1].time)
+        {
+            return Err(CameraPathError::Unordered {
+                path: path.to_path_buf(),
+                index,
+            });
+        }
+        if let Some(index) = camera_path.keyframes.iter().position(|keyframe| !keyframe.has_direction()) {
+            return Err(CameraPathError::Degenerate {
+                path: path.to_path_buf(),
+                index,
+            });
+        }
+        Ok(camera_path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CameraPathError> {
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| CameraPathError::Serialize(e.to_string()))?;
+        std::fs::write(path, source).map_err(|source| CameraPathError::Write {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    // Adds keyframe with current view of the camera after the last one.
+    pub fn add_keyframe(&mut self, camera: &Camera) {
+        let time = self.keyframes.last().map_or(0.0, |last| last.time + KEYFRAME_INTERVAL);
+        self.keyframes.push(Keyframe {
+            time,
+            eye: camera.eye.into(),
+            target: camera.target.into(),
+            fovy: camera.fovy,
+        });
+    }
+
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) if self.looping && self.keyframes.len() > 1 => {
+                last.time - first.time + self.loop_segment_duration()
+            }
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    fn loop_segment_duration(&self) -> f32 {
+        self.keyframes[1].time - self.keyframes[0].time
+    }
+
+    /**
+        Moves camera to where the path is at given time (since start of the path).
+        Camera is not changed when path has no keyframes.
+    */
+    pub fn apply(&self, time: f32, camera: &mut Camera) {
+        let Some(first) = self.keyframes.first() else {
+            return;
+        };
+        let count = self.keyframes.len();
+        if count == 1 {
+            Self::set_camera(first.eye(), first.forward(), first.fovy, camera);
+            return;
+        }
+
+        let duration = self.duration();
+        let time = if self.looping { time.rem_euclid(duration) } else { time.clamp(0.0, duration) };
+        let time = first.time + time;
+        // Segment goes from keyframe `index` to the next one, which is the first one for the loop segment.
+        let (index, start, end) = match self.keyframes.windows(2).position(|pair| time < pair[1].time) {
+            Some(index) => (index, self.keyframes[index].time, self.keyframes[index + 1].time),
+            None if self.looping => (count - 1, self.keyframes[count - 1].time, first.time + duration),
+            None => (count - 2, self.keyframes[count - 2].time, self.keyframes[count - 1].time),
+        };
+        let t = ((time - start) / (end - start)).clamp(0.0, 1.0);
+
+        // Keyframe and its time, times of looping path keep growing past its end.
+        let keyframe = |offset: isize| -> (&Keyframe, f32) {
+            let i = index as isize + offset;
+            if self.looping {
+                let loops = i.div_euclid(count as isize) as f32;
+                let keyframe = &self.keyframes[i.rem_euclid(count as isize) as usize];
+                (keyframe, keyframe.time + loops * duration)
+            } else {
+                let keyframe = &self.keyframes[i.clamp(0, count as isize - 1) as usize];
+                (keyframe, keyframe.time)
+            }
+        };
+        let ((k0, mut t0), (k1, _), (k2, _), (k3, mut t3)) = (keyframe(-1), keyframe(0), keyframe(1), keyframe(2));
+        // Ends of open path repeat the end keyframe, it is placed as far before (after) as the neighbouring one.
+        if t0 >= start {
+            t0 = start - (end - start);
+        }
+        if t3 <= end {
+            t3 = end + (end - start);
+        }
+
+        let eye = catmull_rom([(k0.eye(), t0), (k1.eye(), start), (k2.eye(), end), (k3.eye(), t3)], time.clamp(start, end));
+        let rotation = Quaternion::from_arc(k1.forward().normalize(), k2.forward().normalize(), None);
+        let direction = Quaternion::one().slerp(rotation, t).rotate_vector(k1.forward().normalize());
+        let distance = k1.forward().magnitude() + (k2.forward().magnitude() - k1.forward().magnitude()) * t;
+        let fovy = k1.fovy + (k2.fovy - k1.fovy) * t;
+        Self::set_camera(eye, direction * distance, fovy, camera);
+    }
+
+    fn set_camera(eye: Vector3<f32>, forward: Vector3<f32>, fovy: f32, camera: &mut Camera) {
+        camera.eye = Point3::from_vec(eye);
+        camera.target = Point3::from_vec(eye + forward);
+        camera.fovy = fovy;
+    }
+}
+
+/**
+    Catmull-Rom spline through points placed at given times, evaluated between the middle two
+    (Barry-Goldman pyramid). Parameterised by keyframe times, so the eye moves evenly when keyframes
+    are spaced unevenly in time; with evenly spaced times it is the uniform Catmull-Rom spline.
+*/
+fn catmull_rom(points: [(Vector3<f32>, f32); 4], time: f32) -> Vector3<f32> {
+    let [(p0, t0), (p1, t1), (p2, t2), (p3, t3)] = points;
+    let lerp = |a: Vector3<f32>, ta: f32, b: Vector3<f32>, tb: f32| a * ((tb - time) / (tb - ta)) + b * ((time - ta) / (tb - ta));
+    let a1 = lerp(p0, t0, p1, t1);
+    let a2 = lerp(p1, t1, p2, t2);
+    let a3 = lerp(p2, t2, p3, t3);
+    let b1 = lerp(a1, t0, a2, t2);
+    let b2 = lerp(a2, t1, a3, t3);
+    lerp(b1, t1, b2, t2)
+}
+
+/**
+    Plays camera path: time of the path goes on in fixed updates while it is playing
+    and can be moved (scrubbed) both ways while it is not.
+*/
+#[derive(Default)]
+pub struct CameraPathPlayer {
+    pub path: CameraPath,
+    pub time: f32,
+    pub playing: bool,
+}
+
+impl CameraPathPlayer {
+    pub fn new(path: CameraPath, playing: bool) -> Self {
+        Self {
+            path,
+            time: 0.0,
+            playing,
+        }
+    }
+
+    pub fn toggle_playing(&mut self) {
+        if self.path.keyframes.is_empty() {
+            log::warn!("Camera path has no keyframes");
+            return;
+        }
+        // Path that reached its end starts again.
+        if !self.playing && !self.path.looping && self.time >= self.path.duration() {
+            self.time = 0.0;
+        }
+        self.playing = !self.playing;
+    }
+
+    // Moves time of the path by `delta` seconds, camera follows it.
+    pub fn advance(&mut self, delta: f32, camera: &mut Camera) {
+        let duration = self.path.duration();
+        self.time = if self.path.looping && duration > 0.0 {
+            (self.time + delta).rem_euclid(duration)
+        } else {
+            (self.time + delta).clamp(0.0, duration)
+        };
+        self.path.apply(self.time, camera);
+        if self.playing && !self.path.looping && self.time >= duration {
+            log::info!("Camera path finished");
+            self.playing = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, eye: [f32; 3]) -> Keyframe {
+        Keyframe {
+            time,
+            eye,
+            target: [eye[0], eye[1], eye[2] - 1.0],
+            fovy: 45.0 + time,
+        }
+    }
+
+    fn camera() -> Camera {
+        Camera {
+            eye: Point3::new(0.0, 0.0, 0.0),
+            target: Point3::new(0.0, 0.0, -1.0),
+            up: Vector3::unit_y(),
+            aspect: 1.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+
+    fn eye_at(path: &CameraPath, time: f32) -> [f32; 3] {
+        let mut camera = camera();
+        path.apply(time, &mut camera);
+        camera.eye.into()
+    }
+
+    fn assert_near(a: [f32; 3], b: [f32; 3]) {
+        assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn passes_through_keyframes_and_stops_at_ends() {
+        let path = CameraPath {
+            keyframes: vec![keyframe(1.0, [0.0, 0.0, 0.0]), keyframe(2.0, [1.0, 2.0, 0.0]), keyframe(5.0, [4.0, 0.0, 1.0])],
+            looping: false,
+        };
+        assert_eq!(path.duration(), 4.0);
+        assert_near(eye_at(&path, -1.0), [0.0, 0.0, 0.0]);
+        assert_near(eye_at(&path, 0.0), [0.0, 0.0, 0.0]);
+        assert_near(eye_at(&path, 1.0), [1.0, 2.0, 0.0]);
+        assert_near(eye_at(&path, 4.0), [4.0, 0.0, 1.0]);
+        assert_near(eye_at(&path, 10.0), [4.0, 0.0, 1.0]);
+
+        let mut camera = camera();
+        path.apply(2.5, &mut camera);
+        assert!((camera.fovy - 48.5).abs() < 1e-4);
+        assert!((camera.target - camera.eye).magnitude() > 0.99);
+    }
+
+    #[test]
+    fn looping_path_returns_to_first_keyframe() {
+        let path = CameraPath {
+            keyframes: vec![keyframe(0.0, [0.0, 0.0, 0.0]), keyframe(1.0, [1.0, 0.0, 0.0]), keyframe(3.0, [1.0, 0.0, 2.0])],
+            looping: true,
+        };
+        assert_eq!(path.duration(), 4.0);
+        assert_near(eye_at(&path, 3.0), [1.0, 0.0, 2.0]);
+        assert_near(eye_at(&path, 4.0), [0.0, 0.0, 0.0]);
+        assert_near(eye_at(&path, 5.0), [1.0, 0.0, 0.0]);
+        assert_near(eye_at(&path, -1.0), eye_at(&path, 3.0));
+    }
+
+    #[test]
+    fn uneven_keyframe_times_keep_even_speed() {
+        // Eye moves one unit per second, keyframes around the middle segment are one and three seconds apart.
+        let path = CameraPath {
+            keyframes: vec![
+                keyframe(0.0, [0.0, 0.0, 0.0]),
+                keyframe(1.0, [1.0, 0.0, 0.0]),
+                keyframe(4.0, [4.0, 0.0, 0.0]),
+                keyframe(5.0, [5.0, 0.0, 0.0]),
+            ],
+            looping: false,
+        };
+        for time in [1.0, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0] {
+            assert_near(eye_at(&path, time), [time, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn rejects_degenerate_and_unordered_keyframes() {
+        let path = Path::new("path.ron");
+        let same_eye_and_target = "(keyframes: [
+            (time: 0.0, eye: (0.0, 1.0, 2.0), target: (0.0, 0.0, 0.0), fovy: 45.0),
+            (time: 1.0, eye: (1.0, 1.0, 1.0), target: (1.0, 1.0, 1.0), fovy: 45.0),
+        ])";
+        assert!(matches!(CameraPath::parse(same_eye_and_target, path), Err(CameraPathError::Degenerate { index: 1, .. })));
+        let unordered = "(keyframes: [
+            (time: 1.0, eye: (0.0, 1.0, 2.0), target: (0.0, 0.0, 0.0), fovy: 45.0),
+            (time: 1.0, eye: (1.0, 1.0, 1.0), target: (0.0, 0.0, 0.0), fovy: 45.0),
+        ])";
+        assert!(matches!(CameraPath::parse(unordered, path), Err(CameraPathError::Unordered { index: 1, .. })));
+        let valid = "(keyframes: [(time: 0.0, eye: (0.0, 1.0, 2.0), target: (0.0, 0.0, 0.0), fovy: 45.0)], looping: true)";
+        assert_eq!(CameraPath::parse(valid, path).unwrap().keyframes.len(), 1);
+    }
+}
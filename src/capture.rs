@@ -0,0 +1,214 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::error::RendererError;
+
+/**
+    Copies texture from GPU to CPU memory and returns it as an image.
+    Texture must have COPY_SRC usage and 8 bit rgba format.
+*/
+pub fn read_texture(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Result<image::RgbaImage, RendererError> {
+    let width = texture.width();
+    let height = texture.height();
+    let unpadded_bytes_per_row = 4 * width;
+    // Rows copied from texture to buffer have to be aligned to 256 bytes, so buffer rows
+    // are padded and padding is stripped when copying data out of the buffer.
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    // Mapping is asynchronous, poll with Wait blocks until GPU finished the copy and callback was called.
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv()
+        .map_err(|e| RendererError::Readback(e.to_string()))?
+        .map_err(|e| RendererError::Readback(e.to_string()))?;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| RendererError::Readback("readback data does not match texture size".into()))
+}
+
+/**
+    Writes frames rendered without window as numbered png files (`frame_00000.png`...)
+    to a directory and/or as uncompressed YUV 4:2:0 stream in Y4M format, which can be
+    played or encoded by ffmpeg and most video players.
+*/
+pub struct SequenceWriter {
+    png_directory: Option<PathBuf>,
+    y4m: Option<(PathBuf, BufWriter<File>)>,
+    frame_rate: u32,
+    frame: u32,
+}
+
+impl SequenceWriter {
+    pub fn new(png_directory: Option<PathBuf>, y4m_path: Option<PathBuf>, frame_rate: u32) -> Result<Self, RendererError> {
+        if let Some(directory) = &png_directory {
+            std::fs::create_dir_all(directory).map_err(|source| RendererError::SequenceWrite {
+                path: directory.clone(),
+                source,
+            })?;
+        }
+        let y4m = y4m_path
+            .map(|path| match File::create(&path) {
+                Ok(file) => Ok((path, BufWriter::new(file))),
+                Err(source) => Err(RendererError::SequenceWrite { path, source }),
+            })
+            .transpose()?;
+        Ok(Self {
+            png_directory,
+            y4m,
+            frame_rate,
+            frame: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &image::RgbaImage) -> Result<(), RendererError> {
+        if let Some(directory) = &self.png_directory {
+            frame.save(directory.join(format!("frame_{:05}.png", self.frame))).map_err(RendererError::ImageWrite)?;
+        }
+        if let Some((path, writer)) = &mut self.y4m {
+            let result = (|| {
+                if self.frame == 0 {
+                    // Chroma is subsampled the same way as in jpeg, rgb is converted with full range BT.601.
+                    writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg", frame.width(), frame.height(), self.frame_rate)?;
+                }
+                writeln!(writer, "FRAME")?;
+                writer.write_all(&rgba_to_yuv420(frame))
+            })();
+            result.map_err(|source| RendererError::SequenceWrite {
+                path: path.clone(),
+                source,
+            })?;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), RendererError> {
+        if let Some((path, writer)) = &mut self.y4m {
+            writer.flush().map_err(|source| RendererError::SequenceWrite {
+                path: path.clone(),
+                source,
+            })?;
+            log::info!("Wrote {} frames to {}", self.frame, path.display());
+        }
+        if let Some(directory) = &self.png_directory {
+            log::info!("Wrote {} frames to {}", self.frame, directory.display());
+        }
+        Ok(())
+    }
+}
+
+// Planes of Y, U and V one after another, U and V have half the width and height (rounded up).
+fn rgba_to_yuv420(frame: &image::RgbaImage) -> Vec<u8> {
+    let (width, height) = frame.dimensions();
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut data = Vec::with_capacity((width * height + 2 * chroma_width * chroma_height) as usize);
+    let rgb = |x: u32, y: u32| {
+        let pixel = frame.get_pixel(x.min(width - 1), y.min(height - 1));
+        [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32]
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = rgb(x, y);
+            data.push((0.299 * r + 0.587 * g + 0.114 * b).round() as u8);
+        }
+    }
+    let mut u = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    let mut v = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    for y in 0..chroma_height {
+        for x in 0..chroma_width {
+            let block = [rgb(2 * x, 2 * y), rgb(2 * x + 1, 2 * y), rgb(2 * x, 2 * y + 1), rgb(2 * x + 1, 2 * y + 1)];
+            let [r, g, b] = block.iter().fold([0.0; 3], |sum, c| [sum[0] + c[0] / 4.0, sum[1] + c[1] / 4.0, sum[2] + c[2] / 4.0]);
+            u.push((128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b).round().clamp(0.0, 255.0) as u8);
+            v.push((128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b).round().clamp(0.0, 255.0) as u8);
+        }
+    }
+    data.extend(u);
+    data.extend(v);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odd_sizes_round_chroma_planes_up() {
+        let frame = image::RgbaImage::new(5, 3);
+        assert_eq!(rgba_to_yuv420(&frame).len(), 5 * 3 + 2 * (3 * 2));
+    }
+
+    #[test]
+    fn known_colours_convert_to_full_range_yuv() {
+        let pixel = |colour: [u8; 3]| rgba_to_yuv420(&image::RgbaImage::from_pixel(1, 1, image::Rgba([colour[0], colour[1], colour[2], 255])));
+        assert_eq!(pixel([0, 0, 0]), [0, 128, 128]);
+        assert_eq!(pixel([255, 255, 255]), [255, 128, 128]);
+        assert_eq!(pixel([255, 0, 0]), [76, 85, 255]);
+        assert_eq!(pixel([0, 0, 255]), [29, 255, 107]);
+    }
+
+    #[test]
+    fn chroma_averages_blocks_and_clamps_at_edges() {
+        let red = image::Rgba([255, 0, 0, 255]);
+        let blue = image::Rgba([0, 0, 255, 255]);
+        let frame = image::RgbaImage::from_fn(3, 1, |x, _| if x < 2 { red } else { blue });
+        assert_eq!(rgba_to_yuv420(&frame), [76, 76, 29, 85, 255, 255, 107]);
+    }
+
+    #[test]
+    fn y4m_has_one_header_and_a_marker_per_frame() {
+        let path = std::env::temp_dir().join(format!("wgpu_sandbox_capture_{}.y4m", std::process::id()));
+        let mut writer = SequenceWriter::new(None, Some(path.clone()), 60).unwrap();
+        let frame = image::RgbaImage::from_pixel(3, 3, image::Rgba([255, 255, 255, 255]));
+        writer.write_frame(&frame).unwrap();
+        writer.write_frame(&frame).unwrap();
+        writer.finish().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let header = b"YUV4MPEG2 W3 H3 F60:1 Ip A1:1 C420jpeg\n";
+        let frame_size = b"FRAME\n".len() + 3 * 3 + 2 * (2 * 2);
+        assert!(data.starts_with(header));
+        assert_eq!(data.len(), header.len() + 2 * frame_size);
+        assert!(data[header.len()..].starts_with(b"FRAME\n"));
+        assert!(data[header.len() + frame_size..].starts_with(b"FRAME\n"));
+    }
+}
@@ -0,0 +1,1082 @@
+use crate::texture_format::TextureFormat;
+
+// Features needed to upload block compressed textures as they are.
+pub const COMPRESSION_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC;
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CompressedTextureError {
+    #[error("file is neither KTX2 nor DDS")]
+    UnknownContainer,
+    #[error("file is truncated, {section} ends at byte {end} but file has {size} bytes")]
+    Truncated {
+        section: &'static str,
+        end: usize,
+        size: usize,
+    },
+    #[error("texel format {0} is not supported, only BC1 to BC7 are")]
+    UnsupportedFormat(String),
+    #[error("KTX2 supercompression scheme {0} is not supported")]
+    Supercompressed(u32),
+    #[error("3D textures are not supported")]
+    Volume,
+    #[error("{levels} mip levels are more than {width}x{height} texture can have")]
+    TooManyLevels {
+        levels: u32,
+        width: u32,
+        height: u32,
+    },
+    #[error("{0} is too large")]
+    TooLarge(&'static str),
+    #[error("{0} has premultiplied alpha, which is not supported, use DXT3 or DXT5")]
+    Premultiplied(&'static str),
+}
+
+/**
+    Block compression formats, each block holds 4x4 texels.
+    Signed variants of BC4 and BC5 are not supported.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFormat {
+    // RGB with 1 bit alpha, 8 bytes per block.
+    Bc1,
+    // BC1 without alpha, black texels of three colour blocks are opaque.
+    Bc1Rgb,
+    // BC1 colour with explicit 4 bit alpha.
+    Bc2,
+    // BC1 colour with interpolated alpha.
+    Bc3,
+    // Single channel, 8 bytes per block.
+    Bc4,
+    // Two BC4 channels.
+    Bc5,
+    // HDR RGB with 16 bit float channels.
+    Bc6hUfloat,
+    Bc6hSfloat,
+    // High quality RGB or RGBA.
+    Bc7,
+}
+
+impl BlockFormat {
+    pub fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc1Rgb | BlockFormat::Bc4 => 8,
+            _ => 16,
+        }
+    }
+
+    /**
+        Format GPU samples the blocks as, `None` when there is none that reads the same and blocks
+        have to be decompressed. Only BC1, BC2, BC3 and BC7 have sRGB variants, `srgb` is ignored for the rest.
+    */
+    pub fn wgpu_format(self, srgb: bool) -> Option<wgpu::TextureFormat> {
+        use wgpu::TextureFormat as F;
+        let format = match (self, srgb) {
+            (BlockFormat::Bc1, false) => F::Bc1RgbaUnorm,
+            (BlockFormat::Bc1, true) => F::Bc1RgbaUnormSrgb,
+            (BlockFormat::Bc2, false) => F::Bc2RgbaUnorm,
+            (BlockFormat::Bc2, true) => F::Bc2RgbaUnormSrgb,
+            (BlockFormat::Bc3, false) => F::Bc3RgbaUnorm,
+            (BlockFormat::Bc3, true) => F::Bc3RgbaUnormSrgb,
+            (BlockFormat::Bc4, _) => F::Bc4RUnorm,
+            (BlockFormat::Bc5, _) => F::Bc5RgUnorm,
+            (BlockFormat::Bc6hUfloat, _) => F::Bc6hRgbUfloat,
+            (BlockFormat::Bc6hSfloat, _) => F::Bc6hRgbFloat,
+            (BlockFormat::Bc7, false) => F::Bc7RgbaUnorm,
+            (BlockFormat::Bc7, true) => F::Bc7RgbaUnormSrgb,
+            // Black texels would be transparent in the RGBA format.
+            (BlockFormat::Bc1Rgb, _) => return None,
+        };
+        Some(format)
+    }
+
+    // Format blocks are decompressed to when GPU can't sample them.
+    pub fn decompressed_format(self, srgb: bool) -> TextureFormat {
+        match self {
+            BlockFormat::Bc6hUfloat | BlockFormat::Bc6hSfloat => TextureFormat::Hdr,
+            BlockFormat::Bc1 | BlockFormat::Bc1Rgb | BlockFormat::Bc2 | BlockFormat::Bc3 | BlockFormat::Bc7 if srgb => {
+                TextureFormat::Srgb
+            }
+            _ => TextureFormat::Linear,
+        }
+    }
+}
+
+/**
+    Blocks of a texture loaded from KTX2 or DDS container, with all its layers and mip levels.
+    Faces of a cube map are six layers in order +x, -x, +y, -y, +z, -z.
+*/
+#[derive(Debug, Clone)]
+pub struct CompressedTexture {
+    pub format: BlockFormat,
+    pub srgb: bool,
+    pub width: u32,
+    pub height: u32,
+    pub layers: u32,
+    // Blocks of each mip level starting with the full size one, layers of a level follow each other.
+    pub levels: Vec<Vec<u8>>,
+}
+
+// Both containers start with a magic number, so they can be told apart from images without file extension.
+pub fn is_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(&KTX2_IDENTIFIER) || bytes.starts_with(&DDS_MAGIC)
+}
+
+// Number of layers a texture file adds to the scene texture array, an image adds one.
+pub fn layer_count(bytes: &[u8]) -> Result<u32, CompressedTextureError> {
+    if is_container(bytes) {
+        CompressedTexture::parse(bytes).map(|texture| texture.layers)
+    } else {
+        Ok(1)
+    }
+}
+
+impl CompressedTexture {
+    pub fn parse(bytes: &[u8]) -> Result<Self, CompressedTextureError> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            parse_ktx2(bytes)
+        } else if bytes.starts_with(&DDS_MAGIC) {
+            parse_dds(bytes)
+        } else {
+            Err(CompressedTextureError::UnknownContainer)
+        }
+    }
+
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    // Blocks along width and height, partial blocks at the edges count as whole.
+    pub fn level_blocks(&self, level: usize) -> (u32, u32) {
+        let (width, height) = self.level_size(level);
+        (width.div_ceil(4), height.div_ceil(4))
+    }
+
+    fn level_bytes(&self, level: usize) -> Result<usize, CompressedTextureError> {
+        let (blocks_wide, blocks_high) = self.level_blocks(level);
+        (blocks_wide as usize).checked_mul(blocks_high as usize)
+            .and_then(|blocks| blocks.checked_mul(self.layers as usize))
+            .and_then(|blocks| blocks.checked_mul(self.format.block_bytes()))
+            .ok_or(CompressedTextureError::TooLarge("mip level"))
+    }
+
+    pub fn decompressed_format(&self) -> TextureFormat {
+        self.format.decompressed_format(self.srgb)
+    }
+
+    /**
+        Texels of every mip level in `decompressed_format`, layers of a level follow each other
+        and rows are tightly packed. Parts of the edge blocks outside of the texture are dropped.
+    */
+    pub fn decompress(&self) -> Vec<Vec<u8>> {
+        let texel_bytes = self.decompressed_format().bytes_per_texel() as usize;
+        let block_bytes = self.format.block_bytes();
+        self.levels.iter()
+            .enumerate()
+            .map(|(level, blocks)| {
+                let (width, height) = self.level_size(level);
+                let (width, height) = (width as usize, height as usize);
+                let (blocks_wide, blocks_high) = self.level_blocks(level);
+                let mut texels = vec![0; width * height * self.layers as usize * texel_bytes];
+                let layer_blocks = blocks.chunks_exact(blocks_wide as usize * blocks_high as usize * block_bytes);
+                for (layer_texels, layer_blocks) in texels.chunks_exact_mut(width * height * texel_bytes).zip(layer_blocks) {
+                    for (block_index, block) in layer_blocks.chunks_exact(block_bytes).enumerate() {
+                        let block_x = block_index % blocks_wide as usize * 4;
+                        let block_y = block_index / blocks_wide as usize * 4;
+                        let decoded = decode_block(self.format, block);
+                        for (texel_index, texel) in decoded.chunks_exact(texel_bytes).enumerate() {
+                            let (x, y) = (block_x + texel_index % 4, block_y + texel_index / 4);
+                            if x < width && y < height {
+                                let offset = (y * width + x) * texel_bytes;
+                                layer_texels[offset..offset + texel_bytes].copy_from_slice(texel);
+                            }
+                        }
+                    }
+                }
+                texels
+            })
+            .collect()
+    }
+
+    // Full size level of every layer decompressed to an image, BC6H ones are float images.
+    pub fn to_images(&self) -> Vec<image::DynamicImage> {
+        let level = self.decompress().swap_remove(0);
+        let layer_bytes = level.len() / self.layers as usize;
+        level.chunks_exact(layer_bytes)
+            .map(|texels| match self.decompressed_format() {
+                TextureFormat::Hdr => {
+                    let floats = texels.chunks_exact(2)
+                        .map(|half| f16_bits_to_f32(u16::from_le_bytes([half[0], half[1]])))
+                        .collect();
+                    let image = image::Rgba32FImage::from_raw(self.width, self.height, floats)
+                        .expect("decompressed level has size of the texture");
+                    image::DynamicImage::ImageRgba32F(image)
+                }
+                _ => {
+                    let image = image::RgbaImage::from_raw(self.width, self.height, texels.to_vec())
+                        .expect("decompressed level has size of the texture");
+                    image::DynamicImage::ImageRgba8(image)
+                }
+            })
+            .collect()
+    }
+
+    // Checks that levels fit the texture and keeps only bytes of the blocks in them.
+    fn validated(mut self) -> Result<Self, CompressedTextureError> {
+        check_level_count(self.levels.len() as u32, self.width, self.height)?;
+        for level in 0..self.levels.len() {
+            let needed = self.level_bytes(level)?;
+            if self.levels[level].len() < needed {
+                return Err(CompressedTextureError::Truncated {
+                    section: "mip level",
+                    end: needed,
+                    size: self.levels[level].len(),
+                });
+            }
+            self.levels[level].truncate(needed);
+        }
+        Ok(self)
+    }
+}
+
+// Each level has half the size of the previous one, the last one is 1x1.
+fn check_level_count(levels: u32, width: u32, height: u32) -> Result<(), CompressedTextureError> {
+    if levels > u32::BITS - width.max(height).leading_zeros() {
+        return Err(CompressedTextureError::TooManyLevels { levels, width, height });
+    }
+    Ok(())
+}
+
+fn read_bytes<'a>(bytes: &'a [u8], offset: usize, length: usize, section: &'static str) -> Result<&'a [u8], CompressedTextureError> {
+    bytes.get(offset..offset.saturating_add(length)).ok_or(CompressedTextureError::Truncated {
+        section,
+        end: offset.saturating_add(length),
+        size: bytes.len(),
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize, section: &'static str) -> Result<u32, CompressedTextureError> {
+    let value = read_bytes(bytes, offset, 4, section)?;
+    Ok(u32::from_le_bytes(value.try_into().expect("slice has 4 bytes")))
+}
+
+fn read_u64(bytes: &[u8], offset: usize, section: &'static str) -> Result<u64, CompressedTextureError> {
+    let value = read_bytes(bytes, offset, 8, section)?;
+    Ok(u64::from_le_bytes(value.try_into().expect("slice has 8 bytes")))
+}
+
+/**
+    KTX2 layout: identifier, header, index of sections and of levels, then data of levels.
+    Data of each level holds images of all layers and faces, faces of a layer follow each other.
+    See https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html
+*/
+fn parse_ktx2(bytes: &[u8]) -> Result<CompressedTexture, CompressedTextureError> {
+    let header = |field: usize| read_u32(bytes, 12 + field * 4, "header");
+    let vk_format = header(0)?;
+    let (format, srgb) = match vk_format {
+        131 => (BlockFormat::Bc1Rgb, false),
+        132 => (BlockFormat::Bc1Rgb, true),
+        133 => (BlockFormat::Bc1, false),
+        134 => (BlockFormat::Bc1, true),
+        135 => (BlockFormat::Bc2, false),
+        136 => (BlockFormat::Bc2, true),
+        137 => (BlockFormat::Bc3, false),
+        138 => (BlockFormat::Bc3, true),
+        139 => (BlockFormat::Bc4, false),
+        141 => (BlockFormat::Bc5, false),
+        143 => (BlockFormat::Bc6hUfloat, false),
+        144 => (BlockFormat::Bc6hSfloat, false),
+        145 => (BlockFormat::Bc7, false),
+        146 => (BlockFormat::Bc7, true),
+        _ => return Err(CompressedTextureError::UnsupportedFormat(format!("VkFormat {}", vk_format))),
+    };
+    let width = header(2)?;
+    // Zero height is a 1D texture, zero layers is a texture that isn't an array.
+    let height = header(3)?.max(1);
+    if header(4)? > 1 {
+        return Err(CompressedTextureError::Volume);
+    }
+    let layers = header(5)?.max(1).checked_mul(header(6)?.max(1)).ok_or(CompressedTextureError::TooLarge("number of layers"))?;
+    // Zero levels asks loader to generate them, only the full size one is used then.
+    let level_count = header(7)?.max(1);
+    check_level_count(level_count, width, height)?;
+    let supercompression = header(8)?;
+    if supercompression != 0 {
+        return Err(CompressedTextureError::Supercompressed(supercompression));
+    }
+
+    // Level index follows the header (48 bytes) and index of data format, key/value and global data (32 bytes).
+    let levels = (0..level_count as usize)
+        .map(|level| {
+            let offset = read_u64(bytes, 80 + level * 24, "level index")?;
+            let length = read_u64(bytes, 80 + level * 24 + 8, "level index")?;
+            let offset = usize::try_from(offset).map_err(|_| CompressedTextureError::TooLarge("offset of mip level"))?;
+            let length = usize::try_from(length).map_err(|_| CompressedTextureError::TooLarge("mip level"))?;
+            Ok(read_bytes(bytes, offset, length, "mip level")?.to_vec())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    CompressedTexture { format, srgb, width, height, layers, levels }.validated()
+}
+
+/**
+    DDS layout: magic, header, optional DX10 header when format is given as DXGI format,
+    then all mip levels of the first layer, all mip levels of the second one and so on.
+    See https://learn.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide
+*/
+fn parse_dds(bytes: &[u8]) -> Result<CompressedTexture, CompressedTextureError> {
+    const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+    const DDPF_ALPHAPIXELS: u32 = 0x1;
+    const DDPF_FOURCC: u32 = 0x4;
+    const DDSCAPS2_CUBEMAP: u32 = 0x200;
+    const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+    const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+    const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+
+    let flags = read_u32(bytes, 8, "header")?;
+    let height = read_u32(bytes, 12, "header")?.max(1);
+    let width = read_u32(bytes, 16, "header")?;
+    let level_count = if flags & DDSD_MIPMAPCOUNT != 0 { read_u32(bytes, 28, "header")?.max(1) } else { 1 };
+    let pixel_format_flags = read_u32(bytes, 80, "header")?;
+    let four_cc = read_bytes(bytes, 84, 4, "header")?;
+    let caps2 = read_u32(bytes, 112, "header")?;
+    if caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err(CompressedTextureError::Volume);
+    }
+    if pixel_format_flags & DDPF_FOURCC == 0 {
+        return Err(CompressedTextureError::UnsupportedFormat("uncompressed DDS".into()));
+    }
+
+    let (format, srgb, layers, data_offset) = if four_cc == b"DX10" {
+        let dxgi_format = read_u32(bytes, 128, "DX10 header")?;
+        if read_u32(bytes, 132, "DX10 header")? == D3D10_RESOURCE_DIMENSION_TEXTURE3D {
+            return Err(CompressedTextureError::Volume);
+        }
+        let faces = if read_u32(bytes, 136, "DX10 header")? & DDS_RESOURCE_MISC_TEXTURECUBE != 0 { 6 } else { 1 };
+        let array_size = read_u32(bytes, 140, "DX10 header")?.max(1);
+        let (format, srgb) = match dxgi_format {
+            71 => (BlockFormat::Bc1, false),
+            72 => (BlockFormat::Bc1, true),
+            74 => (BlockFormat::Bc2, false),
+            75 => (BlockFormat::Bc2, true),
+            77 => (BlockFormat::Bc3, false),
+            78 => (BlockFormat::Bc3, true),
+            80 => (BlockFormat::Bc4, false),
+            83 => (BlockFormat::Bc5, false),
+            95 => (BlockFormat::Bc6hUfloat, false),
+            96 => (BlockFormat::Bc6hSfloat, false),
+            98 => (BlockFormat::Bc7, false),
+            99 => (BlockFormat::Bc7, true),
+            _ => return Err(CompressedTextureError::UnsupportedFormat(format!("DXGI format {}", dxgi_format))),
+        };
+        let layers = array_size.checked_mul(faces).ok_or(CompressedTextureError::TooLarge("number of layers"))?;
+        (format, srgb, layers, 148)
+    } else {
+        let format = match four_cc {
+            // Writers set the alpha flag for DXT1 with transparent texels, without it black ones are opaque.
+            b"DXT1" if pixel_format_flags & DDPF_ALPHAPIXELS != 0 => BlockFormat::Bc1,
+            b"DXT1" => BlockFormat::Bc1Rgb,
+            b"DXT3" => BlockFormat::Bc2,
+            b"DXT5" => BlockFormat::Bc3,
+            // Colour of these is multiplied by alpha, it would not read the same as of other textures.
+            b"DXT2" => return Err(CompressedTextureError::Premultiplied("DXT2")),
+            b"DXT4" => return Err(CompressedTextureError::Premultiplied("DXT4")),
+            b"ATI1" | b"BC4U" => BlockFormat::Bc4,
+            b"ATI2" | b"BC5U" => BlockFormat::Bc5,
+            _ => return Err(CompressedTextureError::UnsupportedFormat(
+                format!("FourCC {}", String::from_utf8_lossy(four_cc)),
+            )),
+        };
+        // Old header can't tell which faces are present, cube maps are expected to have all of them.
+        let layers = if caps2 & DDSCAPS2_CUBEMAP != 0 { 6 } else { 1 };
+        (format, false, layers, 128)
+    };
+
+    // Sizes of all levels of one layer are needed to regroup data by level.
+    check_level_count(level_count, width, height)?;
+    let mut texture = CompressedTexture {
+        format,
+        srgb,
+        width,
+        height,
+        layers: 1,
+        levels: vec![Vec::new(); level_count as usize],
+    };
+    let level_bytes = (0..texture.levels.len()).map(|level| texture.level_bytes(level)).collect::<Result<Vec<_>, _>>()?;
+    let mut offset = data_offset;
+    for _ in 0..layers {
+        for (level, length) in level_bytes.iter().enumerate() {
+            texture.levels[level].extend_from_slice(read_bytes(bytes, offset, *length, "mip level")?);
+            // Data up to `offset + length` was just read, so the sum fits.
+            offset += length;
+        }
+    }
+    texture.layers = layers;
+    texture.validated()
+}
+
+// Texels of the block in row major order, in format given by `BlockFormat::decompressed_format`.
+fn decode_block(format: BlockFormat, block: &[u8]) -> Vec<u8> {
+    let texels = match format {
+        BlockFormat::Bc1 => decode_bc1_colour(&block[0..8], true),
+        BlockFormat::Bc1Rgb => decode_bc1_colour(&block[0..8], true).map(|[r, g, b, _]| [r, g, b, 255]),
+        BlockFormat::Bc2 => {
+            let mut texels = decode_bc1_colour(&block[8..16], false);
+            let alpha = u64::from_le_bytes(block[0..8].try_into().expect("block has 16 bytes"));
+            for (index, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (index * 4)) & 0xf) as u8 * 17;
+            }
+            texels
+        }
+        BlockFormat::Bc3 => {
+            let mut texels = decode_bc1_colour(&block[8..16], false);
+            for (texel, alpha) in texels.iter_mut().zip(decode_bc4_channel(&block[0..8])) {
+                texel[3] = alpha;
+            }
+            texels
+        }
+        // Missing channels read the same as from the compressed texture on GPU.
+        BlockFormat::Bc4 => decode_bc4_channel(block).map(|red| [red, 0, 0, 255]),
+        BlockFormat::Bc5 => {
+            let green = decode_bc4_channel(&block[8..16]);
+            let mut texels = decode_bc4_channel(&block[0..8]).map(|red| [red, 0, 0, 255]);
+            for (texel, green) in texels.iter_mut().zip(green) {
+                texel[1] = green;
+            }
+            texels
+        }
+        BlockFormat::Bc6hUfloat | BlockFormat::Bc6hSfloat => {
+            let texels = decode_bc6h(block, format == BlockFormat::Bc6hSfloat);
+            return texels.iter().flatten().flat_map(|half| half.to_le_bytes()).collect();
+        }
+        BlockFormat::Bc7 => decode_bc7(block),
+    };
+    texels.concat()
+}
+
+fn decode_bc1_colour(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+    let colour_0 = u16::from_le_bytes([block[0], block[1]]);
+    let colour_1 = u16::from_le_bytes([block[2], block[3]]);
+    let rgb565 = |colour: u16| {
+        let (r, g, b) = ((colour >> 11) & 0x1f, (colour >> 5) & 0x3f, colour & 0x1f);
+        [(r << 3 | r >> 2) as u8, (g << 2 | g >> 4) as u8, (b << 3 | b >> 2) as u8, 255]
+    };
+    let (end_0, end_1) = (rgb565(colour_0), rgb565(colour_1));
+    let mix = |weight_0: u32, weight_1: u32| -> [u8; 4] {
+        let total = weight_0 + weight_1;
+        std::array::from_fn(|channel| {
+            ((end_0[channel] as u32 * weight_0 + end_1[channel] as u32 * weight_1 + total / 2) / total) as u8
+        })
+    };
+    // BC2 and BC3 always use four colours, BC1 with colour_0 <= colour_1 has three and transparent black.
+    let palette = if colour_0 > colour_1 || !punch_through {
+        [end_0, end_1, mix(2, 1), mix(1, 2)]
+    } else {
+        [end_0, end_1, mix(1, 1), [0, 0, 0, 0]]
+    };
+    let indices = u32::from_le_bytes(block[4..8].try_into().expect("block has 8 bytes"));
+    std::array::from_fn(|texel| palette[(indices >> (texel * 2)) as usize & 0x3])
+}
+
+fn decode_bc4_channel(block: &[u8]) -> [u8; 16] {
+    let (value_0, value_1) = (block[0] as u32, block[1] as u32);
+    let mix = |weight_0: u32, weight_1: u32| {
+        let total = weight_0 + weight_1;
+        ((value_0 * weight_0 + value_1 * weight_1 + total / 2) / total) as u8
+    };
+    // Eight interpolated values, or six of them with exact 0 and 255.
+    let palette = if value_0 > value_1 {
+        [value_0 as u8, value_1 as u8, mix(6, 1), mix(5, 2), mix(4, 3), mix(3, 4), mix(2, 5), mix(1, 6)]
+    } else {
+        [value_0 as u8, value_1 as u8, mix(4, 1), mix(3, 2), mix(2, 3), mix(1, 4), 0, 255]
+    };
+    let mut index_bytes = [0; 8];
+    index_bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+    std::array::from_fn(|texel| palette[(indices >> (texel * 3)) as usize & 0x7])
+}
+
+// Reads bits of a block starting with the least significant bit of its first byte.
+struct BlockBits(u128);
+
+impl BlockBits {
+    fn new(block: &[u8]) -> Self {
+        Self(u128::from_le_bytes(block.try_into().expect("block has 16 bytes")))
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.0 & ((1 << count) - 1)) as u32;
+        self.0 >>= count;
+        value
+    }
+}
+
+// Subset of texels in partitions with 2 subsets, bit n is the subset of texel n. Shared by BC6H and BC7.
+const PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+// Subset of texels in partitions with 3 subsets of BC7.
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2], [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1], [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2], [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2], [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2], [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2], [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2], [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0], [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0], [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2], [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1], [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2], [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2], [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0], [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0], [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1], [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1], [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1], [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1], [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2], [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2], [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2], [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2], [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1], [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2], [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+// Texels whose index has one bit less because its top bit is known to be 0.
+// Texel 0 is the anchor of subset 0 in every partition, these are anchors of the other subsets.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+const ANCHORS_3_SUBSET_1: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+];
+const ANCHORS_3_SUBSET_2: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+// Interpolation weights out of 64 for indices with 2, 3 and 4 bits.
+fn weight(index_bits: u32, index: u32) -> u32 {
+    const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+    const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+    const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+    match index_bits {
+        2 => WEIGHTS_2[index as usize],
+        3 => WEIGHTS_3[index as usize],
+        _ => WEIGHTS_4[index as usize],
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    colour_bits: u32,
+    alpha_bits: u32,
+    // One p-bit (lowest bit of all channels) per endpoint, or one shared by both endpoints of a subset.
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    // Modes 4 and 5 have separate indices for colour and alpha.
+    secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, colour_bits: 4, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 6, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: true, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 5, alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 7, alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, colour_bits: 5, alpha_bits: 6, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, colour_bits: 7, alpha_bits: 8, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, colour_bits: 7, alpha_bits: 7, endpoint_p_bits: true, shared_p_bits: false, index_bits: 4, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, colour_bits: 5, alpha_bits: 5, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+];
+
+/**
+    BC7 block starts with mode given by position of its first set bit, mode decides how many subsets
+    (with their own endpoints) texels are split to and precision of endpoints and indices.
+    See https://registry.khronos.org/DataFormat/specs/1.3/dataformat.1.3.html#bptc_bc7
+*/
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut bits = BlockBits::new(block);
+    let Some(mode) = (0..8).find(|_| bits.read(1) == 1) else {
+        // Block without mode is reserved, it decodes to transparent black.
+        return [[0; 4]; 16];
+    };
+    let mode = &BC7_MODES[mode];
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // Endpoints are stored channel by channel, both endpoints of each subset in every channel.
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    let channels = if mode.alpha_bits > 0 { 4 } else { 3 };
+    for channel in 0..channels {
+        let channel_bits = if channel < 3 { mode.colour_bits } else { mode.alpha_bits };
+        for subset_endpoints in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset_endpoints.iter_mut() {
+                endpoint[channel] = bits.read(channel_bits);
+            }
+        }
+    }
+    let has_p_bit = mode.endpoint_p_bits || mode.shared_p_bits;
+    if mode.endpoint_p_bits {
+        for endpoint in endpoints.iter_mut().take(mode.subsets).flatten() {
+            let p_bit = bits.read(1);
+            endpoint.iter_mut().take(channels).for_each(|value| *value = *value << 1 | p_bit);
+        }
+    }
+    if mode.shared_p_bits {
+        for subset_endpoints in endpoints.iter_mut().take(mode.subsets) {
+            let p_bit = bits.read(1);
+            subset_endpoints.iter_mut()
+                .flat_map(|endpoint| endpoint.iter_mut().take(channels))
+                .for_each(|value| *value = *value << 1 | p_bit);
+        }
+    }
+    // Values are extended to 8 bits by repeating their top bits in the bottom ones.
+    for endpoint in endpoints.iter_mut().take(mode.subsets).flatten() {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            let channel_bits = if channel < 3 { mode.colour_bits } else { mode.alpha_bits } + has_p_bit as u32;
+            *value = if channel_bits == 0 || channel >= channels {
+                255
+            } else {
+                let shifted = *value << (8 - channel_bits);
+                shifted | shifted >> channel_bits
+            };
+        }
+    }
+
+    let subset_of = |texel: usize| match mode.subsets {
+        1 => 0,
+        2 => (PARTITIONS_2[partition] >> texel) as usize & 1,
+        _ => PARTITIONS_3[partition][texel] as usize,
+    };
+    let is_anchor = |texel: usize| {
+        texel == 0 || match mode.subsets {
+            1 => false,
+            2 => texel == ANCHORS_2[partition] as usize,
+            _ => texel == ANCHORS_3_SUBSET_1[partition] as usize || texel == ANCHORS_3_SUBSET_2[partition] as usize,
+        }
+    };
+    let indices: [u32; 16] = std::array::from_fn(|texel| bits.read(mode.index_bits - is_anchor(texel) as u32));
+    // Secondary indices have only one subset, their only anchor is texel 0.
+    let secondary_indices: [u32; 16] = std::array::from_fn(|texel| {
+        if mode.secondary_index_bits == 0 { 0 } else { bits.read(mode.secondary_index_bits - (texel == 0) as u32) }
+    });
+
+    std::array::from_fn(|texel| {
+        let [endpoint_0, endpoint_1] = endpoints[subset_of(texel)];
+        let (primary, secondary) = ((mode.index_bits, indices[texel]), (mode.secondary_index_bits, secondary_indices[texel]));
+        let ((colour_bits, colour_index), (alpha_bits, alpha_index)) = match (mode.secondary_index_bits, index_selection) {
+            (0, _) => (primary, primary),
+            (_, 0) => (primary, secondary),
+            _ => (secondary, primary),
+        };
+        let interpolate = |channel: usize, weight: u32| {
+            ((endpoint_0[channel] * (64 - weight) + endpoint_1[channel] * weight + 32) >> 6) as u8
+        };
+        let colour_weight = weight(colour_bits, colour_index);
+        let alpha_weight = weight(alpha_bits, alpha_index);
+        let mut texel = [interpolate(0, colour_weight), interpolate(1, colour_weight), interpolate(2, colour_weight), interpolate(3, alpha_weight)];
+        // Rotation swaps alpha with one of the colour channels, so the channel with own indices can be any.
+        if rotation > 0 {
+            texel.swap(rotation as usize - 1, 3);
+        }
+        texel
+    })
+}
+
+// Fields of BC6H endpoints, w and x are endpoints of the first region, y and z of the second one.
+const RW: usize = 0;
+const RX: usize = 1;
+const RY: usize = 2;
+const RZ: usize = 3;
+const GW: usize = 4;
+const GX: usize = 5;
+const GY: usize = 6;
+const GZ: usize = 7;
+const BW: usize = 8;
+const BX: usize = 9;
+const BY: usize = 10;
+const BZ: usize = 11;
+// Partition of two region modes.
+const D: usize = 12;
+
+// Field and its bits in the order they follow mode in the block, bits go from the first to the second.
+type Bc6hLayout = &'static [(usize, u32, u32)];
+
+struct Bc6hMode {
+    two_regions: bool,
+    // Other endpoints are stored as differences from the first one.
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    layout: Bc6hLayout,
+}
+
+// Modes by their 2 or 5 bit value, the remaining values are reserved.
+fn bc6h_mode(value: u32) -> Option<Bc6hMode> {
+    let (two_regions, transformed, endpoint_bits, delta_bits, layout): (bool, bool, u32, [u32; 3], Bc6hLayout) = match value {
+        0x00 => (true, true, 10, [5, 5, 5], &[
+            (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (GZ, 4, 4),
+            (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+            (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+        ]),
+        0x01 => (true, true, 7, [6, 6, 6], &[
+            (GY, 5, 5), (GZ, 4, 4), (GZ, 5, 5), (RW, 0, 6), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 6),
+            (BY, 5, 5), (BZ, 2, 2), (GY, 4, 4), (BW, 0, 6), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5),
+            (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+        ]),
+        0x02 => (true, true, 11, [5, 4, 4], &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (RW, 10, 10), (GY, 0, 3), (GX, 0, 3), (GW, 10, 10),
+            (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2),
+            (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+        ]),
+        0x06 => (true, true, 11, [4, 5, 4], &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4),
+            (GW, 10, 10), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 3), (BZ, 0, 0),
+            (BZ, 2, 2), (RZ, 0, 3), (GY, 4, 4), (BZ, 3, 3), (D, 0, 4),
+        ]),
+        0x0a => (true, true, 11, [4, 4, 5], &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (BY, 4, 4), (GY, 0, 3), (GX, 0, 3),
+            (GW, 10, 10), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BW, 10, 10), (BY, 0, 3), (RY, 0, 3), (BZ, 1, 1),
+            (BZ, 2, 2), (RZ, 0, 3), (BZ, 4, 4), (BZ, 3, 3), (D, 0, 4),
+        ]),
+        0x0e => (true, true, 9, [5, 5, 5], &[
+            (RW, 0, 8), (BY, 4, 4), (GW, 0, 8), (GY, 4, 4), (BW, 0, 8), (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4),
+            (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4),
+            (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+        ]),
+        0x12 => (true, true, 8, [6, 5, 5], &[
+            (RW, 0, 7), (GZ, 4, 4), (BY, 4, 4), (GW, 0, 7), (BZ, 2, 2), (GY, 4, 4), (BW, 0, 7), (BZ, 3, 3),
+            (BZ, 4, 4), (RX, 0, 5), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+            (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+        ]),
+        0x16 => (true, true, 8, [5, 6, 5], &[
+            (RW, 0, 7), (BZ, 0, 0), (BY, 4, 4), (GW, 0, 7), (GY, 5, 5), (GY, 4, 4), (BW, 0, 7), (GZ, 5, 5),
+            (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+            (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+        ]),
+        0x1a => (true, true, 8, [5, 5, 6], &[
+            (RW, 0, 7), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 7), (BY, 5, 5), (GY, 4, 4), (BW, 0, 7), (BZ, 5, 5),
+            (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 5),
+            (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3), (D, 0, 4),
+        ]),
+        0x1e => (true, false, 6, [6, 6, 6], &[
+            (RW, 0, 5), (GZ, 4, 4), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 5), (GY, 5, 5), (BY, 5, 5),
+            (BZ, 2, 2), (GY, 4, 4), (BW, 0, 5), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5),
+            (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5), (D, 0, 4),
+        ]),
+        0x03 => (false, false, 10, [10, 10, 10], &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 9), (GX, 0, 9), (BX, 0, 9),
+        ]),
+        0x07 => (false, true, 11, [9, 9, 9], &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 8), (RW, 10, 10), (GX, 0, 8), (GW, 10, 10),
+            (BX, 0, 8), (BW, 10, 10),
+        ]),
+        // Top bits of the first endpoint are stored in reversed order in the last two modes.
+        0x0b => (false, true, 12, [8, 8, 8], &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 7), (RW, 11, 10), (GX, 0, 7), (GW, 11, 10),
+            (BX, 0, 7), (BW, 11, 10),
+        ]),
+        0x0f => (false, true, 16, [4, 4, 4], &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 15, 10), (GX, 0, 3), (GW, 15, 10),
+            (BX, 0, 3), (BW, 15, 10),
+        ]),
+        _ => return None,
+    };
+    Some(Bc6hMode { two_regions, transformed, endpoint_bits, delta_bits, layout })
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+/**
+    BC6H block holds half float RGB texels, its endpoints are integers that are scaled
+    to the range of half floats after interpolation, so their bit patterns are interpolated almost linearly.
+    See https://registry.khronos.org/DataFormat/specs/1.3/dataformat.1.3.html#bptc_bc6h
+*/
+fn decode_bc6h(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    const ONE: u16 = 0x3c00;
+    let mut bits = BlockBits::new(block);
+    let mut mode_value = bits.read(2);
+    if mode_value > 1 {
+        mode_value |= bits.read(3) << 2;
+    }
+    let Some(mode) = bc6h_mode(mode_value) else {
+        // Reserved modes decode to black.
+        return [[0, 0, 0, ONE]; 16];
+    };
+
+    let mut fields = [0u32; 13];
+    for &(field, first, last) in mode.layout {
+        if first <= last {
+            (first..=last).for_each(|bit| fields[field] |= bits.read(1) << bit);
+        } else {
+            (last..=first).rev().for_each(|bit| fields[field] |= bits.read(1) << bit);
+        }
+    }
+
+    let endpoint_count = if mode.two_regions { 4 } else { 2 };
+    let endpoint_bits = mode.endpoint_bits;
+    let mask = (1u32 << endpoint_bits) - 1;
+    // endpoints[channel][w, x, y, z]
+    let endpoints: [[i32; 4]; 3] = std::array::from_fn(|channel| {
+        let raw = &fields[channel * 4..channel * 4 + 4];
+        let mut values = [raw[0] as i32, raw[1] as i32, raw[2] as i32, raw[3] as i32];
+        if signed {
+            values[0] = sign_extend(raw[0], endpoint_bits);
+        }
+        for index in 1..endpoint_count {
+            if mode.transformed {
+                let delta = sign_extend(raw[index], mode.delta_bits[channel]);
+                let value = (values[0] + delta) as u32 & mask;
+                values[index] = if signed { sign_extend(value, endpoint_bits) } else { value as i32 };
+            } else if signed {
+                values[index] = sign_extend(raw[index], endpoint_bits);
+            }
+        }
+        // Endpoints are scaled to 16 bits (15 and sign when signed), the extreme ones exactly.
+        for value in values.iter_mut().take(endpoint_count) {
+            *value = if signed {
+                let magnitude = value.abs();
+                let scaled = if endpoint_bits >= 16 {
+                    magnitude
+                } else if magnitude == 0 {
+                    0
+                } else if magnitude >= (1 << (endpoint_bits - 1)) - 1 {
+                    0x7fff
+                } else {
+                    ((magnitude << 15) + 0x4000) >> (endpoint_bits - 1)
+                };
+                if *value < 0 { -scaled } else { scaled }
+            } else if endpoint_bits >= 15 {
+                *value
+            } else if *value == 0 {
+                0
+            } else if *value == mask as i32 {
+                0xffff
+            } else {
+                ((*value << 16) + 0x8000) >> endpoint_bits
+            };
+        }
+        values
+    });
+
+    let partition = fields[D] as usize;
+    let index_bits = if mode.two_regions { 3 } else { 4 };
+    std::array::from_fn(|texel| {
+        let region = if mode.two_regions { (PARTITIONS_2[partition] >> texel) as usize & 1 } else { 0 };
+        let is_anchor = texel == 0 || (mode.two_regions && texel == ANCHORS_2[partition] as usize);
+        let index_weight = weight(index_bits, bits.read(index_bits - is_anchor as u32));
+        let mut texel = [0, 0, 0, ONE];
+        for (channel, value) in texel.iter_mut().take(3).enumerate() {
+            let (end_0, end_1) = (endpoints[channel][region * 2], endpoints[channel][region * 2 + 1]);
+            let interpolated = (end_0 * (64 - index_weight as i32) + end_1 * index_weight as i32 + 32) >> 6;
+            // Scaling by 31/32 (31/64 for unsigned) maps the largest endpoint to the largest finite half.
+            *value = if !signed {
+                ((interpolated * 31) >> 6) as u16
+            } else if interpolated < 0 {
+                0x8000 | (((-interpolated) * 31) >> 5) as u16
+            } else {
+                ((interpolated * 31) >> 5) as u16
+            };
+        }
+        texel
+    })
+}
+
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Packs (value, bit count) fields into a block, first field in the lowest bits.
+    fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut bits = 0u128;
+        let mut position = 0;
+        for &(value, count) in fields {
+            bits |= (value as u128) << position;
+            position += count;
+        }
+        bits.to_le_bytes().to_vec()
+    }
+
+    fn texel(format: BlockFormat, block: &[u8], index: usize) -> Vec<u8> {
+        let texels = decode_block(format, block);
+        let size = texels.len() / 16;
+        texels[index * size..(index + 1) * size].to_vec()
+    }
+
+    // Header of a 4x4 DDS with given FourCC and, for DX10 ones, DXGI format and array size.
+    fn dds(four_cc: &[u8; 4], dx10: Option<(u32, u32)>) -> Vec<u8> {
+        let mut bytes = vec![0; 148];
+        bytes[0..4].copy_from_slice(&DDS_MAGIC);
+        bytes[12..16].copy_from_slice(&4u32.to_le_bytes());
+        bytes[16..20].copy_from_slice(&4u32.to_le_bytes());
+        bytes[80..84].copy_from_slice(&0x4u32.to_le_bytes());
+        bytes[84..88].copy_from_slice(four_cc);
+        match dx10 {
+            Some((dxgi_format, array_size)) => {
+                bytes[128..132].copy_from_slice(&dxgi_format.to_le_bytes());
+                bytes[132..136].copy_from_slice(&3u32.to_le_bytes());
+                bytes[140..144].copy_from_slice(&array_size.to_le_bytes());
+            }
+            None => bytes.truncate(128),
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_bc1_with_four_and_three_colours() {
+        // Red and black endpoints, texel 0 uses index 2 and texel 1 index 3.
+        let four = [0x00, 0xf8, 0x00, 0x00, 0x0e, 0, 0, 0];
+        assert_eq!(texel(BlockFormat::Bc1, &four, 0), [170, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc1, &four, 1), [85, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc1, &four, 2), [255, 0, 0, 255]);
+        let three = [0x00, 0x00, 0x00, 0xf8, 0x0e, 0, 0, 0];
+        assert_eq!(texel(BlockFormat::Bc1, &three, 0), [128, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc1, &three, 1), [0, 0, 0, 0]);
+        assert_eq!(texel(BlockFormat::Bc1Rgb, &three, 0), [128, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc1Rgb, &three, 1), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rgb_bc1_is_decompressed() {
+        assert_eq!(BlockFormat::Bc1Rgb.wgpu_format(true), None);
+        assert_eq!(BlockFormat::Bc1.wgpu_format(true), Some(wgpu::TextureFormat::Bc1RgbaUnormSrgb));
+
+        let mut bytes = dds(b"DXT1", None);
+        bytes.extend_from_slice(&[0; 8]);
+        assert_eq!(CompressedTexture::parse(&bytes).unwrap().format, BlockFormat::Bc1Rgb);
+        // With the alpha flag.
+        bytes[80] |= 0x1;
+        assert_eq!(CompressedTexture::parse(&bytes).unwrap().format, BlockFormat::Bc1);
+    }
+
+    #[test]
+    fn decodes_bc2_and_bc3_alpha() {
+        let colour = [0x00, 0xf8, 0x00, 0x00, 0x00, 0, 0, 0];
+        let bc2 = [[0x8f, 0, 0, 0, 0, 0, 0, 0], colour].concat();
+        assert_eq!(texel(BlockFormat::Bc2, &bc2, 0), [255, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc2, &bc2, 1), [255, 0, 0, 136]);
+        assert_eq!(texel(BlockFormat::Bc2, &bc2, 2), [255, 0, 0, 0]);
+        // Alpha 70 to 0 in eight steps, texel 0 uses index 2 and texel 1 index 7.
+        let bc3 = [[70, 0, 0x3a, 0, 0, 0, 0, 0], colour].concat();
+        assert_eq!(texel(BlockFormat::Bc3, &bc3, 0), [255, 0, 0, 60]);
+        assert_eq!(texel(BlockFormat::Bc3, &bc3, 1), [255, 0, 0, 10]);
+        assert_eq!(texel(BlockFormat::Bc3, &bc3, 2), [255, 0, 0, 70]);
+    }
+
+    #[test]
+    fn decodes_bc4_and_bc5_channels() {
+        let eight_values = [70, 0, 0x3a, 0, 0, 0, 0, 0];
+        assert_eq!(texel(BlockFormat::Bc4, &eight_values, 0), [60, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc4, &eight_values, 1), [10, 0, 0, 255]);
+        // Values 0 to 50 in six steps, texels 0 to 2 use indices 2, 6 and 7.
+        let six_values = [0, 50, 0xf2, 0x01, 0, 0, 0, 0];
+        assert_eq!(texel(BlockFormat::Bc4, &six_values, 0), [10, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc4, &six_values, 1), [0, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc4, &six_values, 2), [255, 0, 0, 255]);
+        let bc5 = [eight_values, six_values].concat();
+        assert_eq!(texel(BlockFormat::Bc5, &bc5, 0), [60, 10, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc5, &bc5, 1), [10, 0, 0, 255]);
+        assert_eq!(texel(BlockFormat::Bc5, &bc5, 2), [70, 255, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_bc6h_single_region() {
+        // Mode 0x03 with 10 bit endpoints, both endpoints are the same so every texel is too.
+        let block = pack(&[(0x03, 5), (1023, 10), (0, 10), (512, 10), (1023, 10), (0, 10), (512, 10)]);
+        let expected = [0x7bffu16, 0, 0x3e0f, 0x3c00].iter().flat_map(|half| half.to_le_bytes()).collect::<Vec<_>>();
+        assert_eq!(texel(BlockFormat::Bc6hUfloat, &block, 0), expected);
+        assert_eq!(texel(BlockFormat::Bc6hUfloat, &block, 15), expected);
+        // Reserved mode.
+        let reserved = pack(&[(0x13, 5)]);
+        assert_eq!(texel(BlockFormat::Bc6hUfloat, &reserved, 0), [0, 0, 0, 0, 0, 0, 0x00, 0x3c]);
+    }
+
+    #[test]
+    fn decodes_bc7_mode_6() {
+        // Endpoints of each channel, then p-bits of both endpoints, indices are all 0.
+        let block = pack(&[(0x40, 7), (0x7f, 7), (0x7f, 7), (0, 7), (0, 7), (0x40, 7), (0x40, 7), (0x7f, 7), (0x7f, 7), (1, 1), (1, 1)]);
+        assert_eq!(texel(BlockFormat::Bc7, &block, 0), [255, 1, 129, 255]);
+        assert_eq!(texel(BlockFormat::Bc7, &block, 15), [255, 1, 129, 255]);
+        assert_eq!(texel(BlockFormat::Bc7, &[0; 16], 0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn reads_all_layers_of_dds_array() {
+        let mut bytes = dds(b"DX10", Some((71, 2)));
+        bytes.extend_from_slice(&[0x00, 0xf8, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        let texture = CompressedTexture::parse(&bytes).unwrap();
+        assert_eq!(texture.layers, 2);
+        assert_eq!(texture.levels, [bytes[148..164].to_vec()]);
+        assert_eq!(layer_count(&bytes).unwrap(), 2);
+        // Second layer is missing.
+        assert!(matches!(CompressedTexture::parse(&bytes[..156]), Err(CompressedTextureError::Truncated { .. })));
+    }
+
+    #[test]
+    fn rejects_premultiplied_dds() {
+        for four_cc in [b"DXT2", b"DXT4"] {
+            let mut bytes = dds(four_cc, None);
+            bytes.extend_from_slice(&[0; 16]);
+            assert!(matches!(CompressedTexture::parse(&bytes), Err(CompressedTextureError::Premultiplied(_))));
+        }
+    }
+
+    #[test]
+    fn rejects_ktx2_with_too_many_layers() {
+        let mut bytes = vec![0; 104];
+        bytes[0..12].copy_from_slice(&KTX2_IDENTIFIER);
+        let header = [131u32, 1, 4, 4, 0, 0x1_0000, 0x1_0000, 1, 0];
+        for (field, value) in header.iter().enumerate() {
+            bytes[12 + field * 4..16 + field * 4].copy_from_slice(&value.to_le_bytes());
+        }
+        assert!(matches!(CompressedTexture::parse(&bytes), Err(CompressedTextureError::TooLarge(_))));
+    }
+}
@@ -0,0 +1,245 @@
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Point3, Vector3, Vector4};
+
+use crate::tx;
+
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+pub const GREY: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+// Number of segments circles of sphere are made of.
+const CIRCLE_SEGMENTS: u32 = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl DebugVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                }
+            ],
+        }
+    }
+}
+
+/**
+    Immediate mode drawing of lines in world space, meant for debugging (axes, bounds, light directions...).
+
+    Lines are collected every frame with `line`, `aabb`, `sphere`, `axes`, `grid` and `frustum`,
+    `prepare` sends them to the GPU and `draw` draws them in the render pass of the scene.
+    Collected lines are dropped in `prepare` so they have to be added again for the next frame.
+*/
+pub struct DebugDraw {
+    vertices: Vec<DebugVertex>,
+    vertex_buffer: wgpu::Buffer,
+    // Number of vertices the buffer can hold, it grows when there are more lines than that.
+    capacity: u64,
+    // Number of vertices uploaded by last `prepare`.
+    vertex_count: u32,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl DebugDraw {
+    const INITIAL_CAPACITY: u64 = 1024;
+
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/debug_draw.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug Draw Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Draw Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    DebugVertex::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // Lines are hidden behind geometry of the scene but do not hide anything themselves.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: tx::TextureWrapper::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            vertices: Vec::new(),
+            vertex_buffer: Self::create_vertex_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            vertex_count: 0,
+            pipeline,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Draw Vertex Buffer"),
+            size: capacity * std::mem::size_of::<DebugVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+        self.vertices.push(DebugVertex { position: from.into(), color });
+        self.vertices.push(DebugVertex { position: to.into(), color });
+    }
+
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+        let corner = |x: bool, y: bool, z: bool| Point3::new(
+            if x { max.x } else { min.x },
+            if y { max.y } else { min.y },
+            if z { max.z } else { min.z },
+        );
+        for a in [false, true] {
+            for b in [false, true] {
+                // Edges along x, y and z axes.
+                self.line(corner(false, a, b), corner(true, a, b), color);
+                self.line(corner(a, false, b), corner(a, true, b), color);
+                self.line(corner(a, b, false), corner(a, b, true), color);
+            }
+        }
+    }
+
+    // Sphere is drawn as three circles, one around each axis.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 4]) {
+        let axes = [
+            (Vector3::unit_x(), Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+            (Vector3::unit_z(), Vector3::unit_x()),
+        ];
+        for (u, v) in axes {
+            let point = |segment: u32| {
+                let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for segment in 0..CIRCLE_SEGMENTS {
+                self.line(point(segment), point(segment + 1), color);
+            }
+        }
+    }
+
+    // X, y and z axes of given transformation as red, green and blue lines.
+    pub fn axes(&mut self, transform: Matrix4<f32>, length: f32) {
+        let origin = Point3::from_homogeneous(transform * Vector4::new(0.0, 0.0, 0.0, 1.0));
+        for (axis, color) in [(Vector3::unit_x(), RED), (Vector3::unit_y(), GREEN), (Vector3::unit_z(), BLUE)] {
+            let end = Point3::from_homogeneous(transform * (axis * length).extend(1.0));
+            self.line(origin, end, color);
+        }
+    }
+
+    // Grid in xz plane centered in given point, made of `cells` x `cells` squares.
+    pub fn grid(&mut self, center: Point3<f32>, size: f32, cells: u32, color: [f32; 4]) {
+        let half = size / 2.0;
+        for i in 0..=cells {
+            let offset = -half + size * i as f32 / cells as f32;
+            self.line(center + Vector3::new(offset, 0.0, -half), center + Vector3::new(offset, 0.0, half), color);
+            self.line(center + Vector3::new(-half, 0.0, offset), center + Vector3::new(half, 0.0, offset), color);
+        }
+    }
+
+    // Frustum of camera with given view projection matrix (one built by `Camera`).
+    pub fn frustum(&mut self, view_proj: Matrix4<f32>, color: [f32; 4]) {
+        let Some(inverse) = view_proj.invert() else {
+            return;
+        };
+        // Corners of clip space, wgpu depth goes from 0 to 1.
+        let corner = |x: f32, y: f32, z: f32| Point3::from_homogeneous(inverse * Vector4::new(x, y, z, 1.0));
+        let near = [corner(-1.0, -1.0, 0.0), corner(1.0, -1.0, 0.0), corner(1.0, 1.0, 0.0), corner(-1.0, 1.0, 0.0)];
+        let far = [corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(1.0, 1.0, 1.0), corner(-1.0, 1.0, 1.0)];
+        for i in 0..4 {
+            self.line(near[i], near[(i + 1) % 4], color);
+            self.line(far[i], far[(i + 1) % 4], color);
+            self.line(near[i], far[i], color);
+        }
+    }
+
+    /**
+        Uploads lines collected since last call, it has to be called before render pass
+        that draws them is started.
+    */
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let needed = self.vertices.len() as u64;
+        if needed > self.capacity {
+            self.capacity = needed.next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        if !self.vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+        self.vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+
+    // Returns whether anything was drawn.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) -> bool {
+        if self.vertex_count == 0 {
+            return false;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+        true
+    }
+}
@@ -1,4 +1,3 @@
-use wgpu::RenderPass;
 use wgpu::util::DeviceExt;
 use crate::depth_visualisation_bind_group::{create_depth_vis_bind_group, create_depth_vis_bind_group_layout};
 use crate::{tx, vertex};
@@ -7,11 +6,14 @@ pub struct DepthState {
     pub depth_texture: tx::TextureWrapper,
     depth_sampler: wgpu::Sampler,
     bind_group_layout: wgpu::BindGroupLayout,
-    bind_group: wgpu::BindGroup,
+    // None when depth texture can't be sampled, visualisation is not drawn then.
+    bind_group: Option<wgpu::BindGroup>,
     pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
+    sample_count: u32,
+    sampleable: bool,
 }
 
 
@@ -43,6 +45,8 @@ impl DepthState {
     pub fn new(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        sampleable: bool,
     ) -> DepthState {
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -63,8 +67,11 @@ impl DepthState {
 
 
         let depth_texture = tx::TextureWrapper::create_depth_texture(
-            &device, &config, "depth_texture",
+            device, config, sample_count, sampleable, "depth_texture",
         );
+        if !sampleable {
+            log::warn!("Depth texture can't be sampled, depth visualisation is disabled");
+        }
 
         let depth_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -79,12 +86,17 @@ impl DepthState {
             ..Default::default()
         });
 
-        let bind_group_layout = create_depth_vis_bind_group_layout(device);
+        let multisampled = sample_count > 1;
+        let bind_group_layout = create_depth_vis_bind_group_layout(device, multisampled);
 
-        let bind_group = create_depth_vis_bind_group(
-            device, &bind_group_layout, &depth_texture.view, &depth_sampler);
+        let bind_group = sampleable.then(|| create_depth_vis_bind_group(
+            device, &bind_group_layout, &depth_texture.view, &depth_sampler));
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/depth_visualisation_shader.wgsl"));
+        let shader = if multisampled {
+            device.create_shader_module(wgpu::include_wgsl!("shaders/depth_visualisation_msaa_shader.wgsl"))
+        } else {
+            device.create_shader_module(wgpu::include_wgsl!("shaders/depth_visualisation_shader.wgsl"))
+        };
 
         let render_pipeline_layout = device.create_pipeline_layout(
             &wgpu::PipelineLayoutDescriptor {
@@ -148,6 +160,8 @@ impl DepthState {
             vertex_buffer,
             index_buffer,
             num_indices,
+            sample_count,
+            sampleable,
         }
     }
 
@@ -155,8 +169,8 @@ impl DepthState {
                   device: &wgpu::Device,
                   config: &wgpu::SurfaceConfiguration,
     ) {
-        self.depth_texture = tx::TextureWrapper::create_depth_texture(device, config, "depth_texture");
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        self.depth_texture = tx::TextureWrapper::create_depth_texture(device, config, self.sample_count, self.sampleable, "depth_texture");
+        self.bind_group = self.sampleable.then(|| device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &self.bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
@@ -169,18 +183,21 @@ impl DepthState {
                 },
             ],
             label: Some("depth_pass.bind_group"),
-        });
+        }));
     }
 
+    // Returns whether visualisation was drawn.
     pub fn build_render_pass(&mut self,
-                             clear_color: wgpu::Color,
                              encoder: &mut wgpu::CommandEncoder,
                              texture_view: &wgpu::TextureView,
-    ) {
+    ) -> bool {
+        let Some(bind_group) = &self.bind_group else {
+            return false;
+        };
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Depth Visualisation Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &texture_view,
+                view: texture_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Load,
@@ -191,9 +208,10 @@ impl DepthState {
         });
 
         render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(0, bind_group, &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
         render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+        true
     }
 }
\ No newline at end of file
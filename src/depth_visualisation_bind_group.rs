@@ -22,7 +22,8 @@ pub fn create_depth_vis_bind_group(
     )
 }
 
-pub fn create_depth_vis_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+// Multisampled depth texture can't be sampled, shader reads it with textureLoad instead.
+pub fn create_depth_vis_bind_group_layout(device: &wgpu::Device, multisampled: bool) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
@@ -30,7 +31,7 @@ pub fn create_depth_vis_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGr
                 visibility: wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Texture {
                     view_dimension: wgpu::TextureViewDimension::D2,
-                    multisampled: false,
+                    multisampled,
                     sample_type: wgpu::TextureSampleType::Float { filterable: false }
                 },
                 count: None,
@@ -0,0 +1,100 @@
+use crate::camera_path::CameraPathError;
+use crate::compressed_texture::CompressedTextureError;
+use crate::input::InputConfigError;
+use crate::replay::ReplayError;
+use crate::scene::SceneError;
+use crate::texture_format::TextureFormat;
+
+/**
+    Everything that can go wrong while setting up renderer or rendering without window.
+*/
+#[derive(Debug, thiserror::Error)]
+pub enum RendererError {
+    #[error(transparent)]
+    Scene(#[from] SceneError),
+    #[error(transparent)]
+    InputConfig(#[from] InputConfigError),
+    #[error(transparent)]
+    Replay(#[from] ReplayError),
+    #[error(transparent)]
+    CameraPath(#[from] CameraPathError),
+    #[error("could not create window: {0}")]
+    Window(#[from] winit::error::OsError),
+    #[error("could not create surface for the window: {0}")]
+    CreateSurface(#[from] wgpu::CreateSurfaceError),
+    #[error("no suitable adapter found for backends {backends:?}{}", selector_message(.selector))]
+    NoAdapter {
+        backends: wgpu::Backends,
+        selector: Option<String>,
+    },
+    #[error("could not create device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[error("could not decode image of texture {index}: {source}")]
+    ImageDecode {
+        index: usize,
+        #[source]
+        source: image::ImageError,
+    },
+    #[error("could not decode image of skybox: {0}")]
+    SkyboxDecode(#[source] image::ImageError),
+    #[error("could not load compressed texture {index}: {source}")]
+    CompressedTexture {
+        index: usize,
+        #[source]
+        source: CompressedTextureError,
+    },
+    #[error("could not load compressed skybox: {0}")]
+    CompressedSkybox(#[source] CompressedTextureError),
+    #[error("could not decode font atlas: {0}")]
+    FontAtlas(#[source] image::ImageError),
+    #[error("texture {index} has format {format:?}, which is not supported by the adapter")]
+    UnsupportedTextureFormat {
+        index: usize,
+        format: TextureFormat,
+    },
+    #[error("texture layer {layer} has format {format:?}, which can't be mixed with layers of other formats")]
+    MixedTextureFormats {
+        layer: usize,
+        format: TextureFormat,
+    },
+    #[error("invalid cube map: {0}")]
+    CubeFaces(String),
+    #[error("layered texture needs at least one image")]
+    NoImages,
+    #[error("all layers of texture must have same dimensions, layer {layer} is {actual:?} but first layer is {expected:?}")]
+    DimensionMismatch {
+        layer: usize,
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    #[error("{what} is {value} but device limit is {limit}")]
+    LimitExceeded {
+        what: &'static str,
+        value: u32,
+        limit: u32,
+    },
+    #[error("could not create {shader} shader or pipeline: {message}")]
+    ShaderCompilation {
+        shader: &'static str,
+        message: String,
+    },
+    #[error("could not get frame to render to: {0}")]
+    Surface(#[from] wgpu::SurfaceError),
+    #[error("could not read rendered frame back from GPU: {0}")]
+    Readback(String),
+    #[error("could not write image: {0}")]
+    ImageWrite(#[source] image::ImageError),
+    #[error("could not write frame sequence to {path}: {source}")]
+    SequenceWrite {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+fn selector_message(selector: &Option<String>) -> String {
+    match selector {
+        Some(selector) => format!(" matching `{}`", selector),
+        None => String::new(),
+    }
+}
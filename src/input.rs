@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use winit::event::{ElementState, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent};
+
+use crate::render_mode::RenderMode;
+use crate::ron_position;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InputConfigError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{path}:{line}:{column}: {message}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+/**
+    Everything keys and mouse buttons can do. Movement actions last while their binding is held,
+    the rest happen once when it is pressed.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Quit,
+    ToggleDepthVisualisation,
+    SaveScene,
+    ToggleGizmos,
+    ToggleStats,
+    ToggleProfiler,
+    ToggleSettingsPanel,
+    SetRenderMode(RenderMode),
+    AddCameraKeyframe,
+    ToggleCameraPath,
+    ToggleCameraPathLoop,
+    // Scrubbing moves along camera path while held.
+    ScrubCameraPathBackward,
+    ScrubCameraPathForward,
+    SaveCameraPath,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputSource {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+/**
+    Part of window event that input of the sandbox depends on, unlike `WindowEvent` it can be
+    saved and replayed (see `replay`).
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Button {
+        source: InputSource,
+        pressed: bool,
+    },
+    Modifiers(ModifiersState),
+    CursorEntered,
+    CursorLeft,
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => Some(InputEvent::Button {
+                source: InputSource::Key(*key),
+                pressed: *state == ElementState::Pressed,
+            }),
+            WindowEvent::MouseInput { state, button, .. } => Some(InputEvent::Button {
+                source: InputSource::Mouse(*button),
+                pressed: *state == ElementState::Pressed,
+            }),
+            WindowEvent::ModifiersChanged(modifiers) => Some(InputEvent::Modifiers(*modifiers)),
+            WindowEvent::CursorEntered { .. } => Some(InputEvent::CursorEntered),
+            WindowEvent::CursorLeft { .. } => Some(InputEvent::CursorLeft),
+            _ => None,
+        }
+    }
+}
+
+/**
+    Key or mouse button together with modifiers that have to be held with it.
+    In config file it is written as a string like `"W"`, `"Ctrl+Shift+S"` or `"Alt+MouseRight"`,
+    keys are named the same as `VirtualKeyCode` variants.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Binding {
+    pub modifiers: ModifiersState,
+    pub source: InputSource,
+}
+
+// The default input config is the only place default bindings are written down.
+const DEFAULT_CONFIG_NAME: &str = "config/input.ron";
+const DEFAULT_CONFIG: &str = include_str!("../config/input.ron");
+
+const MODIFIER_NAMES: [(ModifiersState, &str); 4] = [
+    (ModifiersState::CTRL, "Ctrl"),
+    (ModifiersState::SHIFT, "Shift"),
+    (ModifiersState::ALT, "Alt"),
+    (ModifiersState::LOGO, "Logo"),
+];
+
+impl FromStr for Binding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.split('+').map(str::trim).collect::<Vec<_>>();
+        let Some(source) = parts.pop().filter(|source| !source.is_empty()) else {
+            return Err(format!("binding `{}` has no key", value));
+        };
+        let mut modifiers = ModifiersState::empty();
+        for part in parts {
+            let Some((modifier, _)) = MODIFIER_NAMES.iter().find(|(_, name)| name.eq_ignore_ascii_case(part)) else {
+                return Err(format!("unknown modifier `{}` in binding `{}`", part, value));
+            };
+            modifiers |= *modifier;
+        }
+        let mouse_button = match source.strip_prefix("Mouse") {
+            Some("Left") => Some(MouseButton::Left),
+            Some("Right") => Some(MouseButton::Right),
+            Some("Middle") => Some(MouseButton::Middle),
+            Some(other) => other.parse().ok().map(MouseButton::Other),
+            None => None,
+        };
+        let source = match mouse_button {
+            Some(button) => InputSource::Mouse(button),
+            // Keys are parsed by the serde implementation of winit, which uses names of the variants.
+            None => InputSource::Key(
+                ron::de::from_str(source).map_err(|_| format!("unknown key `{}` in binding `{}`", source, value))?
+            ),
+        };
+        Ok(Self { modifiers, source })
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in MODIFIER_NAMES {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match self.source {
+            InputSource::Key(key) => write!(f, "{:?}", key),
+            InputSource::Mouse(MouseButton::Other(button)) => write!(f, "Mouse{}", button),
+            InputSource::Mouse(button) => write!(f, "Mouse{:?}", button),
+        }
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        binding.to_string()
+    }
+}
+
+/**
+    Content of input config file. Actions listed in it replace default bindings of these actions,
+    actions that are not listed keep the default ones. Action with empty list of bindings is disabled.
+*/
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputConfig {
+    #[serde(default)]
+    pub bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl InputConfig {
+    pub fn load(path: &Path) -> Result<Self, InputConfigError> {
+        let source = std::fs::read_to_string(path).map_err(|source| InputConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&source, path)
+    }
+
+    pub fn parse(source: &str, path: &Path) -> Result<Self, InputConfigError> {
+        ron_position::from_str(source).map_err(|e| InputConfigError::Parse {
+            path: path.to_path_buf(),
+            line: e.line,
+            column: e.column,
+            message: e.message,
+        })
+    }
+}
+
+/**
+    Maps input events to actions.
+
+    When more bindings of the same key or button match held modifiers, only the ones with most
+    modifiers are used, so `Ctrl+S` does not move the camera when `S` is bound to `MoveBackward`.
+    Releasing key or button releases every action its press started, whatever modifiers are held then.
+*/
+pub struct InputMap {
+    bindings: Vec<(Binding, Action)>,
+    modifiers: ModifiersState,
+    // Actions started by pressed keys and buttons, they are released together with them.
+    held: HashMap<InputSource, Vec<Action>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self::new(&InputConfig::default())
+    }
+}
+
+impl InputMap {
+    pub fn new(config: &InputConfig) -> Self {
+        let mut actions = Self::default_bindings();
+        for (action, bindings) in &config.bindings {
+            actions.insert(*action, bindings.clone());
+        }
+        let mut bindings = actions.into_iter()
+            .flat_map(|(action, bindings)| bindings.into_iter().map(move |binding| (binding, action)))
+            .collect::<Vec<_>>();
+        // Hash map has no order, sorted bindings make actions of one key happen in the same order every run.
+        bindings.sort_by_key(|(binding, action)| (binding.to_string(), format!("{:?}", action)));
+        Self {
+            bindings,
+            modifiers: ModifiersState::empty(),
+            held: HashMap::new(),
+        }
+    }
+
+    // Bindings of config/input.ron as it was when the sandbox was built.
+    pub fn default_bindings() -> HashMap<Action, Vec<Binding>> {
+        InputConfig::parse(DEFAULT_CONFIG, Path::new(DEFAULT_CONFIG_NAME))
+            .expect("Embedded input config is valid")
+            .bindings
+    }
+
+    /**
+        Returns actions started (true) or stopped (false) by the event.
+    */
+    pub fn handle_event(&mut self, event: InputEvent) -> Vec<(Action, bool)> {
+        let (source, pressed) = match event {
+            InputEvent::Modifiers(modifiers) => {
+                self.modifiers = modifiers;
+                return Vec::new();
+            }
+            InputEvent::Button { source, pressed } => (source, pressed),
+            InputEvent::CursorEntered | InputEvent::CursorLeft => return Vec::new(),
+        };
+
+        if pressed {
+            // Key repeat sends more presses without release, the actions are already held then.
+            if self.held.contains_key(&source) {
+                return Vec::new();
+            }
+            let matching = self.bindings.iter()
+                .filter(|(binding, _)| binding.source == source && self.modifiers.contains(binding.modifiers))
+                .collect::<Vec<_>>();
+            let most_modifiers = matching.iter()
+                .map(|(binding, _)| binding.modifiers.bits().count_ones())
+                .max()
+                .unwrap_or_default();
+            let actions = matching.into_iter()
+                .filter(|(binding, _)| binding.modifiers.bits().count_ones() == most_modifiers)
+                .map(|(_, action)| *action)
+                .collect::<Vec<_>>();
+            if actions.is_empty() {
+                return Vec::new();
+            }
+            self.held.insert(source, actions.clone());
+            actions.into_iter().map(|action| (action, true)).collect()
+        } else {
+            self.held.remove(&source)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|action| (action, false))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_binds_every_render_mode() {
+        let bindings = InputMap::default_bindings();
+        assert_eq!(bindings[&Action::MoveForward], vec!["W".parse().unwrap(), "Up".parse().unwrap()]);
+        for mode in RenderMode::ALL {
+            assert!(bindings.contains_key(&Action::SetRenderMode(mode)), "{:?} has no binding", mode);
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip_through_strings() {
+        for text in ["W", "Ctrl+S", "Shift+Alt+MouseLeft", "MouseMiddle", "Mouse4", "Ctrl+Mouse12"] {
+            let binding = text.parse::<Binding>().unwrap();
+            assert_eq!(binding.to_string(), text);
+        }
+        assert_eq!("Mouse4".parse::<Binding>().unwrap().source, InputSource::Mouse(MouseButton::Other(4)));
+        assert!("MouseFoo".parse::<Binding>().is_err());
+        assert!("Hyper+W".parse::<Binding>().is_err());
+    }
+}
@@ -1,13 +1,39 @@
 mod tx;
 mod camera;
 mod main_bind_group;
-mod globals;
 mod main_instance;
 mod depth_state;
 mod depth_visualisation_bind_group;
 mod vertex;
+mod mesh;
+mod capture;
+mod adapter;
+mod debug_draw;
+mod text;
+mod profiler;
+mod ui;
+mod skybox;
+mod ron_position;
+pub mod camera_path;
+pub mod error;
+pub mod input;
+pub mod render_mode;
+pub mod scene;
+pub mod texture_format;
+pub mod options;
+pub mod replay;
+pub mod tone_mapping;
+pub mod compressed_texture;
+pub mod sampler;
+pub mod animation;
+pub mod blend_mode;
+pub mod settings;
+pub mod sprite;
 
-use cgmath::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use wgpu::util::DeviceExt;
 use winit::{
@@ -16,30 +42,37 @@ use winit::{
     window::WindowBuilder,
 };
 
-use winit::window::Window;
+use winit::window::{Fullscreen, Window};
 use crate::main_bind_group::{create_main_bind_group, create_main_bind_group_layout};
 use crate::camera::{Camera, CameraController, CameraUniform};
+use crate::camera_path::{CameraPath, CameraPathPlayer};
+use crate::compressed_texture::CompressedTexture;
+use crate::debug_draw::DebugDraw;
 use crate::depth_state::DepthState;
+use crate::error::RendererError;
+use crate::input::{Action, InputConfig, InputEvent, InputMap};
+use crate::main_instance::TransparentInstances;
+use crate::mesh::MeshBuffers;
+use crate::options::Options;
+use crate::profiler::Profiler;
+use crate::render_mode::{MainPipelines, RenderMode};
+use crate::replay::{Player, Recorder, Recording, ReplayEvent, FIXED_TIMESTEP};
+use crate::scene::{Scene, SceneDescription, SkyboxImage, TextureDescription};
+use crate::settings::SettingsEdit;
+use crate::skybox::Skybox;
+use crate::sprite::SpriteBatch;
+use crate::text::TextRenderer;
+use crate::tone_mapping::ToneMappingPass;
 use crate::tx::TextureWrapper;
-
-const NUM_INSTANCES_PER_ROW: u32 = 10;
-const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(NUM_INSTANCES_PER_ROW as f32 * 0.5, 0.0, NUM_INSTANCES_PER_ROW as f32 * 0.5);
-
-
-const VERTICES: &[vertex::Vertex] = &[
-    vertex::Vertex { position: [0.0, 0.0, 0.0], tex_coords: [0.0, 0.0] },
-    vertex::Vertex { position: [0.5, 0.0, 0.0], tex_coords: [1.0, 0.0] },
-    vertex::Vertex { position: [0.5, 0.5, 0.0], tex_coords: [1.0, 1.0] },
-    vertex::Vertex { position: [0.0, 0.5, 0.0], tex_coords: [0.0, 1.0] },
-];
-
-const INDICES: &[u16] = &[
-    0, 1, 2,
-    2, 3, 0,
-];
+use crate::ui::Ui;
 
 struct State {
-    surface: wgpu::Surface,
+    // There is no surface when rendering without window, frames are rendered to offscreen_target then.
+    surface: Option<wgpu::Surface>,
+    offscreen_target: Option<TextureWrapper>,
+    // Multisampled texture main pass renders to, only present when MSAA is on.
+    msaa_texture: Option<TextureWrapper>,
+    sample_count: u32,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
@@ -47,13 +80,13 @@ struct State {
     // The window must be declared after the surface so
     // it gets dropped after it as the surface contains
     // unsafe references to the window's resources.
-    window: Window,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
+    window: Option<Window>,
+    main_pipelines: MainPipelines,
+    render_mode: RenderMode,
+    meshes: MeshBuffers,
     cursor_in: bool,
-    layered_texture: TextureWrapper,
+    clear_color_cursor_in: wgpu::Color,
+    clear_color_cursor_out: wgpu::Color,
     depth_visualisation: bool,
 
     // this is responsible for drawing everything but camera
@@ -66,20 +99,63 @@ struct State {
     camera_controller: CameraController,
     instances: Vec<main_instance::MainInstance>,
     instance_buffer: wgpu::Buffer,
-    nearest_sampler: wgpu::Sampler,
-    linear_sampler: wgpu::Sampler,
+    // Range of instances in instance buffer drawn with given mesh
+    instance_ranges: Vec<std::ops::Range<u32>>,
+    transparent_instances: TransparentInstances,
     depth_state: depth_state::DepthState,
+    tone_mapping: ToneMappingPass,
+    skybox: Skybox,
+    debug_draw: DebugDraw,
+    // Whether axes, grid, bounds of instances and lights are drawn with debug_draw.
+    show_gizmos: bool,
+    text: TextRenderer,
+    // Whether FPS, camera position and render mode are written over the scene.
+    show_stats: bool,
+    sprites: SpriteBatch,
+    profiler: Profiler,
+    // Settings panel, there is none without window.
+    ui: Option<Ui>,
+    input_map: InputMap,
+    // Maps live input while recording is replayed, only quitting is handled then.
+    live_input_map: InputMap,
+    // Input handled while recording is saved when event loop ends.
+    recorder: Option<Recorder>,
+    // Live input is ignored while recording is replayed.
+    player: Option<Player>,
+    // Number of fixed updates done so far.
+    tick: u64,
+    // Time since last update that fixed updates did not use yet.
+    tick_accumulator: Duration,
+    last_update: Option<Instant>,
+    exit_requested: bool,
+    // Camera follows the path while it is played or scrubbed, on top of the camera controller.
+    camera_path: CameraPathPlayer,
+    // -1 while path is scrubbed backward, 1 while it is scrubbed forward.
+    camera_path_scrub: f32,
+
+    // Scene as it was loaded, used as a base when saving current state of the scene.
+    scene_description: SceneDescription,
+    scene_path: PathBuf,
+    // CPU side copies of everything needed to create renderer again when device is lost.
+    texture_bytes: Vec<Vec<u8>>,
+    texture_layers: u32,
+    skybox_bytes: Vec<Vec<u8>>,
+    options: Options,
+    device_lost: Arc<AtomicBool>,
 }
 
 impl State {
     // ...
-    async fn new(window: Window) -> Self {
-        let size = window.inner_size();
+    async fn new(window: Option<Window>, scene: &Scene, options: &Options) -> Result<Self, RendererError> {
+        let size = match &window {
+            Some(window) => window.inner_size(),
+            None => winit::dpi::PhysicalSize::new(options.width, options.height),
+        };
 
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
+            backends: options.backend.to_wgpu(),
             dx12_shader_compiler: Default::default(),
         });
 
@@ -88,20 +164,37 @@ impl State {
         //
         // The surface needs to live as long as the window that created it.
         // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let surface = match &window {
+            Some(window) => Some(unsafe { instance.create_surface(window) }?),
+            None => None,
+        };
 
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.unwrap();
+        let adapter = adapter::select_adapter(
+            &instance,
+            options.backend.to_wgpu(),
+            options.adapter.as_ref(),
+            options.power_preference.to_wgpu(),
+            surface.as_ref(),
+        ).await.ok_or_else(|| RendererError::NoAdapter {
+            backends: options.backend.to_wgpu(),
+            selector: options.adapter.as_ref().map(ToString::to_string),
+        })?;
+        adapter::log_capability_report(&adapter);
 
+        let use_binding_array = main_bind_group::binding_arrays_supported(adapter.features());
+        if !use_binding_array {
+            log::warn!("Adapter does not support texture binding arrays, textures are bound as single texture_2d_array");
+        }
+
+        let mut features = adapter.features()
+            & (render_mode::WIREFRAME_FEATURES | Profiler::TIMESTAMP_FEATURES | compressed_texture::COMPRESSION_FEATURES
+                | texture_format::R16_UNORM_FEATURES);
+        if use_binding_array {
+            features |= main_bind_group::BINDING_ARRAY_FEATURES;
+        }
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                // Those features features are required if we want to bind storage array (texture array) as uniform.
-                features: wgpu::Features::TEXTURE_BINDING_ARRAY | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY,
+                features,
                 // WebGL doesn't support all of wgpu's features, so if
                 // we're building for the web we'll have to disable some.
                 limits: if cfg!(target_arch = "wasm32") {
@@ -112,103 +205,112 @@ impl State {
                 label: None,
             },
             None, // Trace path
-        ).await.unwrap();
-
-        let linear_sampler_desc = wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        };
+        ).await?;
 
-        let nearest_sampler_desc = wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        };
+        // There is no device lost callback in this version of wgpu, lost device is reported
+        // as an error of the operation that was running when it happened.
+        let device_lost = Arc::new(AtomicBool::new(false));
+        {
+            let device_lost = device_lost.clone();
+            device.on_uncaptured_error(Box::new(move |error| {
+                if is_device_lost_error(&error) {
+                    log::error!("Device lost: {}", error);
+                    device_lost.store(true, Ordering::SeqCst);
+                } else {
+                    // This is what default handler does.
+                    panic!("wgpu error: {}", error);
+                }
+            }));
+        }
 
-        let surface_caps = surface.get_capabilities(&adapter);
-        // Shader code in this tutorial assumes an sRGB surface texture. Using a different
-        // one will result all the colors coming out darker. If you want to support non
-        // sRGB surfaces, you'll need to account for that when drawing to the frame.
-        let surface_format = surface_caps.formats.iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
+        let config = match &surface {
+            Some(surface) => {
+                let surface_caps = surface.get_capabilities(&adapter);
+                // Tone mapping pass encodes colours itself when surface is not sRGB, but text and ui
+                // shaders assume an sRGB surface texture, on others they come out darker.
+                let surface_format = surface_caps.formats.iter()
+                    .copied()
+                    .find(|f| f.is_srgb())
+                    .unwrap_or(surface_caps.formats[0]);
+                let requested_present_mode = options.present_mode.to_wgpu();
+                // Fifo is the only mode that is guaranteed to be supported everywhere.
+                let present_mode = if surface_caps.present_modes.contains(&requested_present_mode) {
+                    requested_present_mode
+                } else {
+                    log::warn!("Present mode {:?} is not supported, using Fifo", requested_present_mode);
+                    wgpu::PresentMode::Fifo
+                };
+                wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    format: surface_format,
+                    width: size.width,
+                    height: size.height,
+                    present_mode,
+                    alpha_mode: surface_caps.alpha_modes[0],
+                    view_formats: vec![],
+                }
+            }
+            // Without window there is nothing to configure but the rest of renderer still
+            // uses this config to know size and format of frames.
+            None => wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                width: size.width,
+                height: size.height,
+                present_mode: wgpu::PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+            },
+        };
+        if let Some(surface) = &surface {
+            surface.configure(&device, &config);
+        }
+        let offscreen_target = match surface {
+            Some(_) => None,
+            None => Some(TextureWrapper::create_render_target(&device, &config)),
         };
-        surface.configure(&device, &config);
 
-        let linear_sampler = device.create_sampler(&linear_sampler_desc);
-        let nearest_sampler = device.create_sampler(&nearest_sampler_desc);
+        // Only main pass is multisampled and it renders to HDR target.
+        let sample_count = Self::supported_sample_count(&adapter, TextureWrapper::HDR_FORMAT, options.msaa);
+        let msaa_texture = (sample_count > 1)
+            .then(|| TextureWrapper::create_msaa_texture(&device, &config, TextureWrapper::HDR_FORMAT, sample_count));
 
-        let grass_bytes = include_bytes!("assets/grass.png");
-        let cobblestone_bytes = include_bytes!("assets/cobblestone.png");
+        let samplers = scene.description.samplers.iter()
+            .map(|sampler| sampler.create_sampler(&device))
+            .collect::<Vec<_>>();
 
-        // So here apparently include_bytes! returns type &[u8; N] and it actually picks up file
-        // in compile time so N is number of actual bytes in image (crazy!).
-        // Therefore provided byte arrays have different types (one is &[u8; x], and other is &[u8; y])
-        // by taking slices here we convert them to common type.
-        // Rust blows my mind sometimes.
-        let all_texture_bytes = [&grass_bytes[..], &cobblestone_bytes[..]];
+        let all_texture_bytes = scene.texture_bytes.iter()
+            .map(Vec::as_slice)
+            .collect::<Vec<_>>();
+        let texture_formats = scene.description.textures.iter()
+            .map(TextureDescription::format)
+            .collect::<Vec<_>>();
         let layered_texture = TextureWrapper::multilayer_from_bytes(
-            &device, &queue, &all_texture_bytes, "grass.png",
-        ).unwrap();
+            &device, &queue, &all_texture_bytes, &texture_formats, "scene_textures",
+        )?;
+        if use_binding_array && scene.texture_layers > device.limits().max_sampled_textures_per_shader_stage {
+            return Err(RendererError::LimitExceeded {
+                what: "number of textures in binding array",
+                value: scene.texture_layers,
+                limit: device.limits().max_sampled_textures_per_shader_stage,
+            });
+        }
 
         let bind_group_layout = create_main_bind_group_layout(
             &device,
-            all_texture_bytes.len() as u32,
+            scene.texture_layers,
+            samplers.len(),
+            use_binding_array,
         );
 
         let bind_group = create_main_bind_group(
             &device, &bind_group_layout, &layered_texture.view,
-            &linear_sampler, &nearest_sampler,
+            &samplers, use_binding_array,
         );
 
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
+        let meshes = MeshBuffers::new(&device, &scene.description.build_meshes());
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
-        let num_indices = INDICES.len() as u32;
-
-        let camera = Camera {
-            // position the camera one unit up and 2 units back
-            // +z is out of the screen
-            eye: (0.0, 1.0, 2.0).into(),
-            // have it look at the origin
-            target: (0.0, 0.0, 0.0).into(),
-            // which way is "up"
-            up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
-        };
+        let camera = scene.description.build_camera(config.width as f32 / config.height as f32);
 
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
@@ -248,132 +350,119 @@ impl State {
             label: Some("camera_bind_group"),
         });
 
-        let depth_state = DepthState::new(&device, &config);
-
+        // GL backend can't create multisampled depth texture that can be bound to shader,
+        // only multisampled render attachments work there.
+        let depth_sampleable = sample_count == 1 || adapter.get_info().backend != wgpu::Backend::Gl;
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let depth_state = DepthState::new(&device, &config, sample_count, depth_sampleable);
+        if let Some(error) = device.pop_error_scope().await {
+            return Err(RendererError::ShaderCompilation {
+                shader: "depth visualisation",
+                message: error.to_string(),
+            });
+        }
 
-        let instances = (0..NUM_INSTANCES_PER_ROW).flat_map(|z| {
-            (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-                let position = cgmath::Vector3 { x: x as f32, y: 0.0, z: NUM_INSTANCES_PER_ROW as f32 - z as f32 } - INSTANCE_DISPLACEMENT;
-                println!("position {} {} {}", position.x, position.y, position.z);
-                let rotation = if position.is_zero() {
-                    // this is needed so an object at (0, 0, 0) won't get scaled to zero
-                    // as Quaternions can effect scale if they're not created correctly
-                    cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
-                } else {
-                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
-                };
 
-                main_instance::MainInstance {
-                    position,
-                    rotation,
-                    use_linear_sampler: z % 2 == 0,
-                    texture_index: {
-                        if z % 2 == 0 {
-                            1
-                        } else {
-                            0
-                        }
-                    },
-                }
-            })
-        }).collect::<Vec<_>>();
-        let instance_data = instances.iter().map(main_instance::MainInstance::to_raw).collect::<Vec<_>>();
+        let instances = scene.description.build_instances();
+        let (instance_data, instance_ranges) = main_instance::build_instance_data(
+            &instances, meshes.ranges.len(),
+        );
         let instance_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Instance Buffer"),
                 contents: bytemuck::cast_slice(&instance_data),
-                usage: wgpu::BufferUsages::VERTEX,
+                // Instances can be changed in settings panel.
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             }
         );
+        let transparent_instances = TransparentInstances::new(&device);
 
 
-        // this is the same as:
-        // let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        //     label: Some("Shader"),
-        //     source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into()),
-        // });
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/shader.wgsl"));
+        let environment = Self::create_environment(&device, &queue, scene)?;
 
-        let render_pipeline_layout = device.create_pipeline_layout(
-            &wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[
-                    &bind_group_layout,
-                    &camera_bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            }
-        );
+        // Without error scope wgpu panics on invalid shader, with it we can report what went wrong.
+        // Everything in the scope is created in a closure so that the scope is popped
+        // even when creating something fails.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let created = (|| {
+            let shader = main_bind_group::create_main_shader(&device, &scene.description.samplers, use_binding_array);
+            let reflectivity = scene.description.skybox.as_ref().map_or(0.0, |skybox| skybox.reflectivity);
+            let skybox = Skybox::new(&device, &queue, environment, reflectivity, TextureWrapper::HDR_FORMAT, sample_count)?;
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    vertex::Vertex::desc(),
-                    main_instance::MainInstanceRaw::desc()
-                ],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: {
-                Some(wgpu::DepthStencilState {
-                    format: tx::TextureWrapper::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                })
-            },
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+            let render_pipeline_layout = device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("Render Pipeline Layout"),
+                    bind_group_layouts: &[
+                        &bind_group_layout,
+                        &camera_bind_group_layout,
+                        skybox.bind_group_layout(),
+                    ],
+                    push_constant_ranges: &[],
+                }
+            );
 
-        let cursor_in = true;
+            let main_pipelines = MainPipelines::new(
+                &device, &render_pipeline_layout, &shader, TextureWrapper::HDR_FORMAT, sample_count,
+            );
+            let debug_draw = DebugDraw::new(&device, &camera_bind_group_layout, TextureWrapper::HDR_FORMAT, sample_count);
+            let renderer_settings = &scene.description.renderer;
+            let tone_mapping = ToneMappingPass::new(
+                &device, &config, renderer_settings.tone_mapping, renderer_settings.exposure, renderer_settings.gamma,
+            );
+            let text = TextRenderer::new(&device, &queue, config.format)?;
+            let sprite_shader = main_bind_group::create_sprite_shader(&device, &scene.description.samplers, use_binding_array);
+            let sprites = SpriteBatch::new(
+                &device, &bind_group_layout, &sprite_shader, config.format,
+                [layered_texture.texture.width(), layered_texture.texture.height()],
+                scene.description.sprite_sampler,
+            );
+            let profiler = Profiler::new(&device, &queue, options.profile);
+            let ui = window.as_ref().map(|window| Ui::new(window, &device, config.format));
+            Ok::<_, RendererError>((skybox, main_pipelines, debug_draw, tone_mapping, text, sprites, profiler, ui))
+        })();
+        let scope_error = device.pop_error_scope().await;
+        let (skybox, main_pipelines, debug_draw, tone_mapping, text, sprites, profiler, ui) = created?;
+        if let Some(error) = scope_error {
+            return Err(RendererError::ShaderCompilation {
+                shader: "main",
+                message: error.to_string(),
+            });
+        }
 
-        let texture_swap = false;
+        let cursor_in = true;
+        let (clear_color_cursor_in, clear_color_cursor_out) = scene.description.clear_colors();
 
-        let camera_controller = CameraController::new(0.2);
+        let camera_controller = CameraController::new(scene.description.camera.speed);
+        let input_config = match &options.input_config {
+            Some(path) => InputConfig::load(path)?,
+            None => InputConfig::default(),
+        };
+        let input_map = InputMap::new(&input_config);
+        let live_input_map = InputMap::new(&input_config);
+        let recorder = options.record.clone().map(Recorder::new);
+        let player = options.replay.as_deref().map(Recording::load).transpose()?.map(Player::new);
+        let camera_path = match &options.camera_path {
+            Some(path) => CameraPathPlayer::new(CameraPath::load(path)?, true),
+            None => CameraPathPlayer::default(),
+        };
 
-        return Self {
+        Ok(Self {
             window,
             surface,
+            offscreen_target,
+            msaa_texture,
+            sample_count,
             device,
             queue,
             config,
             size,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
+            main_pipelines,
+            render_mode: scene.description.renderer.render_mode,
+            meshes,
             cursor_in,
-            layered_texture,
-            depth_visualisation: texture_swap,
+            clear_color_cursor_in,
+            clear_color_cursor_out,
+            depth_visualisation: scene.description.renderer.depth_visualisation,
             main_bind_group: bind_group,
             camera,
             camera_uniform,
@@ -382,14 +471,136 @@ impl State {
             camera_controller,
             instances,
             instance_buffer,
-            linear_sampler,
-            nearest_sampler,
+            instance_ranges,
+            transparent_instances,
             depth_state,
+            tone_mapping,
+            skybox,
+            debug_draw,
+            show_gizmos: scene.description.renderer.debug_draw,
+            text,
+            show_stats: scene.description.renderer.stats_overlay,
+            sprites,
+            profiler,
+            ui,
+            input_map,
+            live_input_map,
+            recorder,
+            player,
+            tick: 0,
+            tick_accumulator: Duration::ZERO,
+            last_update: None,
+            exit_requested: false,
+            camera_path,
+            camera_path_scrub: 0.0,
+            scene_description: scene.description.clone(),
+            scene_path: scene.path.clone(),
+            texture_bytes: scene.texture_bytes.clone(),
+            texture_layers: scene.texture_layers,
+            skybox_bytes: scene.skybox_bytes.clone(),
+            options: options.clone(),
+            device_lost,
+        })
+    }
+
+    fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::SeqCst)
+    }
+
+    fn mark_device_lost(&self) {
+        self.device_lost.store(true, Ordering::SeqCst);
+    }
+
+    /**
+        Recreates device, queue and every GPU object after device was lost.
+        All of it is built again by `State::new` from snapshot of current scene so camera,
+        instances and toggles survive. The window, input recording and replay, camera path,
+        profiler statistics and settings panel state are carried over to the new state.
+    */
+    async fn recover_from_device_loss(mut self) -> Result<State, RendererError> {
+        log::warn!("Recreating renderer after device loss");
+        let scene = Scene {
+            path: self.scene_path.clone(),
+            description: self.scene_snapshot(),
+            texture_bytes: std::mem::take(&mut self.texture_bytes),
+            texture_layers: self.texture_layers,
+            skybox_bytes: std::mem::take(&mut self.skybox_bytes),
+        };
+        let options = self.options.clone();
+        let cursor_in = self.cursor_in;
+        let window = self.window.take();
+        let input_map = std::mem::take(&mut self.input_map);
+        let live_input_map = std::mem::take(&mut self.live_input_map);
+        let recorder = self.recorder.take();
+        let player = self.player.take();
+        let tick = self.tick;
+        let camera_path = std::mem::take(&mut self.camera_path);
+        let camera_path_scrub = self.camera_path_scrub;
+        let ui_state = self.ui.as_ref().map(Ui::state);
+        let mut profiler = std::mem::take(&mut self.profiler);
+        profiler.lose_device();
+        // Everything created with the old device (and the surface of the window) has to be
+        // gone before new instance and device are created.
+        drop(self);
+
+        let mut state = State::new(window, &scene, &options).await?;
+        state.cursor_in = cursor_in;
+        // Recording and replay go on as if nothing happened.
+        state.input_map = input_map;
+        state.live_input_map = live_input_map;
+        state.recorder = recorder;
+        state.player = player;
+        state.tick = tick;
+        state.camera_path = camera_path;
+        state.camera_path_scrub = camera_path_scrub;
+        if let (Some(ui), Some(ui_state)) = (&mut state.ui, ui_state) {
+            ui.restore_state(ui_state);
+        }
+        profiler.set_device(&state.device, &state.queue);
+        state.profiler = profiler;
+        log::info!("Renderer recreated");
+        Ok(state)
+    }
+
+    fn supported_sample_count(adapter: &wgpu::Adapter, format: wgpu::TextureFormat, requested: u32) -> u32 {
+        let color_flags = adapter.get_texture_format_features(format).flags;
+        let depth_flags = adapter.get_texture_format_features(TextureWrapper::DEPTH_FORMAT).flags;
+        if color_flags.sample_count_supported(requested) && depth_flags.sample_count_supported(requested) {
+            requested
+        } else {
+            log::warn!("MSAA with {} samples is not supported by adapter, MSAA is disabled", requested);
+            1
+        }
+    }
+
+    // Cube map of the skybox, None when scene has no skybox.
+    fn create_environment(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+    ) -> Result<Option<TextureWrapper>, RendererError> {
+        let Some(skybox) = &scene.description.skybox else {
+            return Ok(None);
         };
+        if let SkyboxImage::Cube(_) = &skybox.image {
+            let compressed = CompressedTexture::parse(&scene.skybox_bytes[0]).map_err(RendererError::CompressedSkybox)?;
+            return Ok(Some(TextureWrapper::cube_from_compressed(device, queue, &compressed, Some("skybox"))?));
+        }
+        let images = scene.skybox_bytes.iter()
+            .map(|bytes| tx::decode_image(bytes).map_err(RendererError::SkyboxDecode))
+            .collect::<Result<Vec<_>, _>>()?;
+        let environment = match &skybox.image {
+            SkyboxImage::Faces(_) => TextureWrapper::cube_from_images(device, queue, &images, Some("skybox"))?,
+            SkyboxImage::Equirectangular { face_size, .. } => {
+                TextureWrapper::cube_from_equirectangular(device, queue, &images[0], *face_size, Some("skybox"))?
+            }
+            SkyboxImage::Cube(_) => unreachable!("compressed cube map is loaded above"),
+        };
+        Ok(Some(environment))
     }
 
     pub fn window(&self) -> &Window {
-        &self.window
+        self.window.as_ref().expect("State has no window when rendering headless")
     }
 
     // todo: test if it even works
@@ -398,50 +609,448 @@ impl State {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            if self.msaa_texture.is_some() {
+                self.msaa_texture = Some(TextureWrapper::create_msaa_texture(
+                    &self.device, &self.config, TextureWrapper::HDR_FORMAT, self.sample_count,
+                ));
+            }
         }
         self.depth_state.resize(&self.device, &self.config);
+        self.tone_mapping.resize(&self.device, &self.config);
     }
 
+    fn scene_snapshot(&self) -> SceneDescription {
+        let mut description = self.scene_description.with_runtime_state(
+            &self.camera, self.camera_controller.speed(), &self.instances,
+        );
+        description.set_clear_colors(self.clear_color_cursor_in, self.clear_color_cursor_out);
+        description.renderer.depth_visualisation = self.depth_visualisation;
+        description.renderer.render_mode = self.render_mode;
+        description.renderer.debug_draw = self.show_gizmos;
+        description.renderer.stats_overlay = self.show_stats;
+        description.renderer.tone_mapping = self.tone_mapping.tone_mapping;
+        description.renderer.exposure = self.tone_mapping.exposure;
+        description.renderer.gamma = self.tone_mapping.gamma;
+        if let Some(skybox) = &mut description.skybox {
+            skybox.reflectivity = self.skybox.reflectivity;
+        }
+        description
+    }
+
+    fn save_scene(&self) {
+        let path = scene::save_path(&self.scene_path);
+        match self.scene_snapshot().save(&path) {
+            Ok(()) => log::info!("Saved scene to {}", path.display()),
+            Err(e) => log::error!("Failed to save scene: {}", e),
+        }
+    }
+
+    /**
+        Returns true when event was used by settings panel or as input. Events used by settings
+        panel are not mapped to actions, what they change is recorded by `edit_settings` instead.
+    */
     fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_events(event)
+        if self.ui.as_mut().is_some_and(|ui| ui.on_event(event)) {
+            return true;
+        }
+        let Some(input) = InputEvent::from_window_event(event) else {
+            return false;
+        };
+        // Live input would change outcome of the replay, but a long replay can still be quit.
+        if self.player.is_some() {
+            if self.live_input_map.handle_event(input).contains(&(Action::Quit, true)) {
+                self.exit_requested = true;
+            }
+            return true;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.tick, ReplayEvent::Input(input));
+        }
+        self.apply_input(input);
+        true
+    }
+
+    // Settings panel edits go through here, so they are recorded like input.
+    fn edit_settings(&mut self, edit: SettingsEdit) {
+        // Live edits would change outcome of the replay.
+        if self.player.is_some() {
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.tick, ReplayEvent::Settings(edit));
+        }
+        self.apply_settings_edit(edit);
+    }
+
+    fn apply_settings_edit(&mut self, edit: SettingsEdit) {
+        match edit {
+            SettingsEdit::Action(action) => self.handle_action(action, true),
+            SettingsEdit::Fovy(fovy) => self.camera.fovy = fovy,
+            SettingsEdit::Znear(znear) => self.camera.znear = znear.min(self.camera.zfar * 0.5),
+            SettingsEdit::Zfar(zfar) => {
+                self.camera.zfar = zfar;
+                self.camera.znear = self.camera.znear.min(zfar * 0.5);
+            }
+            SettingsEdit::CameraSpeed(speed) => self.camera_controller.set_speed(speed),
+            SettingsEdit::ClearCameraPath => self.camera_path = Default::default(),
+            SettingsEdit::CameraPathTime(time) => self.camera_path.advance(time - self.camera_path.time, &mut self.camera),
+            SettingsEdit::ToneMapping(tone_mapping) => self.tone_mapping.tone_mapping = tone_mapping,
+            SettingsEdit::Exposure(exposure) => self.tone_mapping.exposure = exposure,
+            SettingsEdit::Gamma(gamma) => self.tone_mapping.gamma = gamma,
+            SettingsEdit::Reflectivity(reflectivity) => self.skybox.reflectivity = reflectivity,
+            SettingsEdit::ClearColor { cursor_in, rgb } => {
+                let color = if cursor_in { &mut self.clear_color_cursor_in } else { &mut self.clear_color_cursor_out };
+                color.r = rgb[0] as f64;
+                color.g = rgb[1] as f64;
+                color.b = rgb[2] as f64;
+            }
+            SettingsEdit::InstancePosition { index, position } => {
+                if let Some(instance) = self.instances.get_mut(index) {
+                    instance.position = position.into();
+                    self.write_instances();
+                }
+            }
+            SettingsEdit::InstanceTexture { index, texture_index } => {
+                let texture_count = self.texture_layers as i32;
+                if let Some(instance) = self.instances.get_mut(index) {
+                    instance.texture_index = texture_index.clamp(0, texture_count - 1);
+                    self.write_instances();
+                }
+            }
+            SettingsEdit::InstanceSampler { index, sampler_index } => {
+                let sampler_count = self.scene_description.samplers.len() as i32;
+                if let Some(instance) = self.instances.get_mut(index).filter(|_| (0..sampler_count).contains(&sampler_index)) {
+                    instance.sampler_index = sampler_index;
+                    self.write_instances();
+                }
+            }
+        }
+    }
+
+    fn apply_input(&mut self, input: InputEvent) {
+        match input {
+            InputEvent::CursorEntered => self.cursor_in = true,
+            InputEvent::CursorLeft => self.cursor_in = false,
+            _ => {
+                for (action, is_pressed) in self.input_map.handle_event(input) {
+                    self.handle_action(action, is_pressed);
+                }
+            }
+        }
+    }
+
+    fn handle_action(&mut self, action: Action, is_pressed: bool) {
+        if self.camera_controller.process_action(action, is_pressed) {
+            return;
+        }
+        match action {
+            Action::ScrubCameraPathBackward => self.camera_path_scrub = if is_pressed { -1.0 } else { 0.0 },
+            Action::ScrubCameraPathForward => self.camera_path_scrub = if is_pressed { 1.0 } else { 0.0 },
+            _ if !is_pressed => {}
+            Action::ToggleDepthVisualisation => self.depth_visualisation = !self.depth_visualisation,
+            Action::SaveScene => self.save_scene(),
+            Action::ToggleGizmos => self.show_gizmos = !self.show_gizmos,
+            Action::ToggleStats => self.show_stats = !self.show_stats,
+            Action::ToggleProfiler => self.toggle_profiler(),
+            Action::ToggleSettingsPanel => {
+                if let Some(ui) = &mut self.ui {
+                    ui.visible = !ui.visible;
+                }
+            }
+            Action::SetRenderMode(render_mode) => {
+                log::info!("Render mode: {:?}", render_mode);
+                self.render_mode = render_mode;
+            }
+            Action::Quit => self.exit_requested = true,
+            Action::AddCameraKeyframe => {
+                self.camera_path.path.add_keyframe(&self.camera);
+                log::info!("Added camera keyframe {}", self.camera_path.path.keyframes.len());
+            }
+            Action::ToggleCameraPath => self.camera_path.toggle_playing(),
+            Action::ToggleCameraPathLoop => self.camera_path.path.looping = !self.camera_path.path.looping,
+            Action::SaveCameraPath => self.save_camera_path(),
+            Action::MoveForward | Action::MoveBackward | Action::MoveLeft | Action::MoveRight => {}
+        }
+    }
+
+    fn save_camera_path(&self) {
+        let path = &self.options.camera_path_output;
+        match self.camera_path.path.save(path) {
+            Ok(()) => log::info!("Saved camera path to {}", path.display()),
+            Err(e) => log::error!("Failed to save camera path: {}", e),
+        }
+    }
+
+    fn write_instances(&self) {
+        let (instance_data, _) = main_instance::build_instance_data(&self.instances, self.meshes.ranges.len());
+        self.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instance_data));
+    }
+
+    fn run_ui(&mut self) {
+        let (Some(ui), Some(window)) = (&mut self.ui, &self.window) else {
+            return;
+        };
+        let Some((context, input)) = ui.begin_frame(window) else {
+            return;
+        };
+        let mut edits = Vec::new();
+        let view = self.settings_view();
+        let output = context.run(input, |context| ui::settings_panel(context, &view, &mut edits));
+        if let (Some(ui), Some(window)) = (&mut self.ui, &self.window) {
+            ui.end_frame(window, output);
+        }
+        for edit in edits {
+            self.edit_settings(edit);
+        }
+    }
+
+    // Current settings shown by the settings panel.
+    fn settings_view(&self) -> ui::SettingsView<'_> {
+        ui::SettingsView {
+            fovy: self.camera.fovy,
+            znear: self.camera.znear,
+            zfar: self.camera.zfar,
+            camera_speed: self.camera_controller.speed(),
+            camera_path: &self.camera_path,
+            render_mode: self.render_mode,
+            depth_visualisation: self.depth_visualisation,
+            show_gizmos: self.show_gizmos,
+            show_stats: self.show_stats,
+            tone_mapping: self.tone_mapping.tone_mapping,
+            exposure: self.tone_mapping.exposure,
+            gamma: self.tone_mapping.gamma,
+            reflectivity: self.skybox.is_visible().then_some(self.skybox.reflectivity),
+            clear_color_cursor_in: self.clear_color_cursor_in,
+            clear_color_cursor_out: self.clear_color_cursor_out,
+            texture_layers: self.texture_layers,
+            samplers: &self.scene_description.samplers,
+            instances: &self.instances,
+        }
     }
 
     fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
+        self.profiler.begin_frame();
+        let update_start = Instant::now();
+        self.run_ui();
+        for _ in 0..self.due_ticks(update_start) {
+            self.fixed_update();
+        }
         self.camera_uniform.update_view_proj(&self.camera);
+        self.camera_uniform.time = self.tick as f32 * FIXED_TIMESTEP.as_secs_f32();
         self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+        self.skybox.update(&self.queue, &self.camera);
+        self.tone_mapping.update(&self.queue);
+        if self.show_gizmos {
+            self.draw_gizmos();
+        }
+        if self.show_stats {
+            self.draw_stats();
+        }
+        for sprite in &self.scene_description.sprites {
+            self.sprites.set_sampler(sprite.sampler.unwrap_or(self.scene_description.sprite_sampler));
+            self.sprites.draw_sprite(sprite.texture_index, sprite.rect, &sprite.transform, sprite.tint);
+        }
+        self.sprites.set_sampler(self.scene_description.sprite_sampler);
+        self.profiler.record_update(update_start);
+    }
+
+    /**
+        Number of fixed updates that fit in the time since last update. Without window every frame
+        is one update, so headless rendering does not depend on how fast frames are rendered.
+    */
+    fn due_ticks(&mut self, now: Instant) -> u32 {
+        const MAX_TICKS_PER_FRAME: u32 = 8;
+        if self.window.is_none() {
+            return 1;
+        }
+        let Some(last_update) = self.last_update.replace(now) else {
+            return 1;
+        };
+        self.tick_accumulator += now - last_update;
+        let ticks = (self.tick_accumulator.as_nanos() / FIXED_TIMESTEP.as_nanos()) as u32;
+        if ticks > MAX_TICKS_PER_FRAME {
+            // Frame took too long (window was dragged, debugger stopped...), the rest of the time is dropped.
+            self.tick_accumulator = Duration::ZERO;
+            return MAX_TICKS_PER_FRAME;
+        }
+        self.tick_accumulator -= FIXED_TIMESTEP * ticks;
+        ticks
+    }
+
+    fn fixed_update(&mut self) {
+        if let Some(player) = &mut self.player {
+            let events = player.take_due(self.tick);
+            let finished = player.is_finished(self.tick);
+            for event in events {
+                match event {
+                    ReplayEvent::Input(input) => self.apply_input(input),
+                    ReplayEvent::Settings(edit) => self.apply_settings_edit(edit),
+                }
+            }
+            if finished {
+                log::info!("Replay finished after {} updates", self.tick);
+                self.player = None;
+            }
+        }
+        self.camera_controller.update_camera(&mut self.camera);
+        if self.camera_path.playing || self.camera_path_scrub != 0.0 {
+            let speed = if self.camera_path.playing { 1.0 } else { 0.0 } + self.camera_path_scrub;
+            self.camera_path.advance(speed * FIXED_TIMESTEP.as_secs_f32(), &mut self.camera);
+        }
+        self.tick += 1;
+    }
+
+    fn save_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.save(self.tick) {
+                Ok(path) => log::info!("Saved input recording to {}", path.display()),
+                Err(e) => log::error!("Failed to save input recording: {}", e),
+            }
+        }
+    }
+
+    fn toggle_profiler(&mut self) {
+        let enabled = !self.profiler.is_enabled();
+        self.profiler.set_enabled(enabled);
+        if enabled {
+            log::info!("Profiler enabled");
+        } else {
+            self.write_trace();
+        }
+    }
+
+    fn write_trace(&self) {
+        match self.profiler.write_chrome_trace(&self.options.trace_output) {
+            Ok(()) => log::info!("Wrote profiler trace to {}", self.options.trace_output.display()),
+            Err(e) => log::error!("Failed to write profiler trace: {}", e),
+        }
+    }
+
+    fn draw_stats(&mut self) {
+        let frame_time = self.profiler.average_frame_time().as_secs_f32();
+        let fps = if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 };
+        let eye = self.camera.eye;
+        let mut stats = format!(
+            "FPS: {:.1} ({:.2} ms)\nCamera: ({:.2}, {:.2}, {:.2})\nRender mode: {:?}",
+            fps, frame_time * 1000.0, eye.x, eye.y, eye.z, self.render_mode,
+        );
+        if let Some(last_frame) = self.profiler.history().last() {
+            stats.push_str(&format!("\nDraw calls: {}, instances: {}", last_frame.draw_calls, last_frame.instances));
+            for (pass, duration) in &last_frame.gpu_passes {
+                stats.push_str(&format!("\nGPU {}: {:.3} ms", pass, duration.as_secs_f64() * 1000.0));
+            }
+        }
+        self.text.text([8.0, 8.0], 0.6, &stats, text::WHITE);
+    }
+
+    fn draw_gizmos(&mut self) {
+        use cgmath::{EuclideanSpace, Matrix4, Point3, SquareMatrix, Vector3};
+        self.debug_draw.grid(Point3::origin(), 20.0, 20, debug_draw::GREY);
+        self.debug_draw.axes(Matrix4::identity(), 1.0);
+
+        for instance in &self.instances {
+            let (min, max) = self.meshes.ranges[instance.mesh_index].bounds;
+            let model = Matrix4::from_translation(instance.position) * Matrix4::from(instance.rotation);
+            // Box containing all corners of mesh bounds after transformation.
+            let mut world_min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+            let mut world_max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+            for corner in 0..8 {
+                let local = Point3::new(
+                    if corner & 1 == 0 { min.x } else { max.x },
+                    if corner & 2 == 0 { min.y } else { max.y },
+                    if corner & 4 == 0 { min.z } else { max.z },
+                );
+                let world = Point3::from_homogeneous(model * local.to_homogeneous());
+                world_min = Point3::new(world_min.x.min(world.x), world_min.y.min(world.y), world_min.z.min(world.z));
+                world_max = Point3::new(world_max.x.max(world.x), world_max.y.max(world.y), world_max.z.max(world.z));
+            }
+            self.debug_draw.aabb(world_min, world_max, debug_draw::GREEN);
+        }
+
+        let view_proj = self.camera.build_view_projection_matrix();
+        let screen_size = [self.config.width as f32, self.config.height as f32];
+        for (index, instance) in self.instances.iter().enumerate() {
+            let position = Point3::from_vec(instance.position);
+            self.text.label(position, view_proj, screen_size, &format!("#{}", index), text::YELLOW);
+        }
+
+        let camera_path = &self.camera_path.path;
+        if camera_path.keyframes.len() > 1 {
+            const PATH_SEGMENTS: u32 = 128;
+            let mut camera = self.scene_description.build_camera(self.camera.aspect);
+            let mut previous = None;
+            for segment in 0..=PATH_SEGMENTS {
+                camera_path.apply(camera_path.duration() * segment as f32 / PATH_SEGMENTS as f32, &mut camera);
+                if let Some(previous) = previous {
+                    self.debug_draw.line(previous, camera.eye, debug_draw::YELLOW);
+                }
+                previous = Some(camera.eye);
+            }
+            for keyframe in &camera_path.keyframes {
+                self.debug_draw.line(keyframe.eye.into(), keyframe.target.into(), debug_draw::BLUE);
+            }
+        }
+
+        // Frustum of the camera the scene starts with, visible once camera moves away from it.
+        let start_camera = self.scene_description.build_camera(self.camera.aspect);
+        self.debug_draw.frustum(start_camera.build_view_projection_matrix(), debug_draw::GREY);
+
+        for light in &self.scene_description.lights {
+            match light {
+                scene::LightDescription::Point { position, .. } => {
+                    self.debug_draw.sphere((*position).into(), 0.1, debug_draw::YELLOW);
+                }
+                scene::LightDescription::Directional { direction, .. } => {
+                    // Directional light has no position, its direction is shown as coming to the origin.
+                    let direction = Vector3::from(*direction);
+                    self.debug_draw.line(Point3::origin() - direction * 2.0, Point3::origin(), debug_draw::YELLOW);
+                }
+            }
+        }
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let render_start = Instant::now();
+        let output = match &self.surface {
+            Some(surface) => Some(surface.get_current_texture()?),
+            None => None,
+        };
+        let view = match (&output, &self.offscreen_target) {
+            (Some(output), _) => output.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            (None, Some(target)) => target.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            (None, None) => unreachable!("State has neither surface nor offscreen target"),
+        };
+        // Main pass renders to HDR target, with MSAA on to multisampled texture which is then resolved to it.
+        let hdr_view = &self.tone_mapping.hdr_target.view;
+        let (color_view, resolve_target) = match &self.msaa_texture {
+            Some(msaa_texture) => (&msaa_texture.view, Some(hdr_view)),
+            None => (hdr_view, None),
+        };
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
 
+        self.debug_draw.prepare(&self.device, &self.queue);
+        self.text.prepare(&self.device, &self.queue, [self.config.width as f32, self.config.height as f32]);
+        self.sprites.prepare(&self.device, &self.queue, [self.config.width as f32, self.config.height as f32]);
+        self.transparent_instances.prepare(&self.device, &self.queue, &self.instances, &self.meshes.ranges, self.camera.eye);
+
         let clear_color: wgpu::Color = if self.cursor_in {
-            wgpu::Color {
-                r: 0.1,
-                g: 0.2,
-                b: 0.3,
-                a: 1.0,
-            }
+            self.clear_color_cursor_in
         } else {
-            wgpu::Color {
-                r: 0.2,
-                g: 0.5,
-                b: 0.1,
-                a: 1.0,
-            }
+            self.clear_color_cursor_out
         };
         {
+            let main_pass_timestamp = self.profiler.begin_pass(&mut encoder, "main");
             {
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: Some("Render Pass"),
                     color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
+                        view: color_view,
+                        resolve_target,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Clear(clear_color),
                             store: true,
@@ -457,14 +1066,76 @@ impl State {
                     }),
                 });
 
-                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_pipeline(self.main_pipelines.get(self.render_mode));
                 render_pass.set_bind_group(0, &self.main_bind_group, &[]);
                 render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass.set_bind_group(2, self.skybox.bind_group(), &[]);
                 render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-                render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+                let unindexed = self.main_pipelines.needs_unindexed_meshes(self.render_mode);
+                self.meshes.set_buffers(&mut render_pass, unindexed);
+                for (mesh_index, instances) in self.instance_ranges.iter().enumerate() {
+                    if !instances.is_empty() {
+                        self.meshes.draw(&mut render_pass, mesh_index, instances.clone(), unindexed);
+                        self.profiler.record_draw(instances.len() as u32);
+                    }
+                }
+                // Debug render modes draw transparent instances like opaque ones.
+                if self.render_mode != RenderMode::Shaded && !self.transparent_instances.draws.is_empty() {
+                    render_pass.set_vertex_buffer(1, self.transparent_instances.buffer().slice(..));
+                    for draw in &self.transparent_instances.draws {
+                        self.meshes.draw(&mut render_pass, draw.mesh_index, draw.instances.clone(), unindexed);
+                        self.profiler.record_draw(draw.instances.len() as u32);
+                    }
+                }
+                // Sky goes after instances so it is only drawn where they did not write depth.
+                if self.skybox.draw(&mut render_pass) {
+                    self.profiler.record_draw(1);
+                }
+                if self.debug_draw.draw(&mut render_pass, &self.camera_bind_group) {
+                    self.profiler.record_draw(1);
+                }
             }
+            self.profiler.end_pass(&mut encoder, main_pass_timestamp);
+            if self.render_mode == RenderMode::Shaded && !self.transparent_instances.draws.is_empty() {
+                let transparent_pass_timestamp = self.profiler.begin_pass(&mut encoder, "transparent");
+                {
+                    // Continues where main pass ended, on top of its colour and depth.
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Transparent Render Pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: color_view,
+                            resolve_target,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                            view: &self.depth_state.depth_texture.view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        }),
+                    });
+                    render_pass.set_bind_group(0, &self.main_bind_group, &[]);
+                    render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                    render_pass.set_bind_group(2, self.skybox.bind_group(), &[]);
+                    render_pass.set_vertex_buffer(1, self.transparent_instances.buffer().slice(..));
+                    self.meshes.set_buffers(&mut render_pass, false);
+                    for draw in &self.transparent_instances.draws {
+                        render_pass.set_pipeline(self.main_pipelines.transparent(draw.blend_mode));
+                        self.meshes.draw(&mut render_pass, draw.mesh_index, draw.instances.clone(), false);
+                        self.profiler.record_draw(draw.instances.len() as u32);
+                    }
+                }
+                self.profiler.end_pass(&mut encoder, transparent_pass_timestamp);
+            }
+            let tone_mapping_timestamp = self.profiler.begin_pass(&mut encoder, "tone mapping");
+            self.tone_mapping.build_render_pass(&mut encoder, &view);
+            self.profiler.record_draw(1);
+            self.profiler.end_pass(&mut encoder, tone_mapping_timestamp);
             // submit will accept anything that implements IntoIter
             self.queue.submit(std::iter::once(encoder.finish()));
         }
@@ -473,89 +1144,326 @@ impl State {
             let mut depth_command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
-            self.depth_state.build_render_pass(clear_color, &mut depth_command_encoder, &view);
+            let depth_pass_timestamp = self.profiler.begin_pass(&mut depth_command_encoder, "depth visualisation");
+            if self.depth_state.build_render_pass(&mut depth_command_encoder, &view) {
+                self.profiler.record_draw(1);
+            }
+            self.profiler.end_pass(&mut depth_command_encoder, depth_pass_timestamp);
             self.queue.submit(std::iter::once(depth_command_encoder.finish()));
         }
 
-        output.present();
+        let mut overlay_command_encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Overlay Encoder"),
+        });
+        let sprite_pass_timestamp = self.profiler.begin_pass(&mut overlay_command_encoder, "sprites");
+        let sprites = self.sprites.build_render_pass(&mut overlay_command_encoder, &view, &self.main_bind_group);
+        if sprites > 0 {
+            self.profiler.record_draw(sprites);
+        }
+        self.profiler.end_pass(&mut overlay_command_encoder, sprite_pass_timestamp);
+        let text_pass_timestamp = self.profiler.begin_pass(&mut overlay_command_encoder, "text");
+        let glyphs = self.text.build_render_pass(&mut overlay_command_encoder, &view);
+        if glyphs > 0 {
+            self.profiler.record_draw(glyphs);
+        }
+        self.profiler.end_pass(&mut overlay_command_encoder, text_pass_timestamp);
+        if let Some(ui) = &mut self.ui {
+            let ui_pass_timestamp = self.profiler.begin_pass(&mut overlay_command_encoder, "ui");
+            let draw_calls = ui.build_render_pass(
+                &self.device, &self.queue, &mut overlay_command_encoder, &view,
+                [self.config.width, self.config.height],
+            );
+            self.profiler.record_draws(draw_calls, draw_calls);
+            self.profiler.end_pass(&mut overlay_command_encoder, ui_pass_timestamp);
+        }
+        self.queue.submit(std::iter::once(overlay_command_encoder.finish()));
+        self.profiler.end_frame(&self.device, &self.queue, render_start);
+
+        if let Some(output) = output {
+            output.present();
+        }
 
         Ok(())
     }
 }
 
-pub async fn run() {
+// Lost device is reported as validation error with `DeviceError::Lost` of wgpu-core among its sources.
+fn is_device_lost_error(error: &wgpu::Error) -> bool {
+    let wgpu::Error::Validation { source, .. } = error else {
+        return false;
+    };
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(source.as_ref());
+    while let Some(error) = source {
+        if matches!(error.downcast_ref::<wgpu::core::device::DeviceError>(), Some(wgpu::core::device::DeviceError::Lost)) {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraPose {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+}
+
+pub struct HeadlessOutput {
+    pub frame: image::RgbaImage,
+    // Camera after update of every frame.
+    pub camera_path: Vec<CameraPose>,
+}
+
+/**
+    Renders requested number of frames without window (or more, when replayed recording is longer)
+    and returns the last one.
+*/
+pub async fn render_headless(scene: &Scene, options: &Options) -> Result<HeadlessOutput, RendererError> {
+    render_headless_with_sprites(scene, options, |_| {}).await
+}
+
+// Like `render_headless`, `draw_sprites` adds sprites to every frame after those of the scene.
+pub async fn render_headless_with_sprites(
+    scene: &Scene,
+    options: &Options,
+    mut draw_sprites: impl FnMut(&mut SpriteBatch),
+) -> Result<HeadlessOutput, RendererError> {
+    let mut state = State::new(None, scene, options).await?;
+    let frames = state.player.as_ref().map_or(0, Player::ticks).max(options.frames as u64);
+    let mut sequence = match (&options.sequence_output, &options.y4m_output) {
+        (None, None) => None,
+        (png_directory, y4m_path) => Some(capture::SequenceWriter::new(
+            png_directory.clone(), y4m_path.clone(), Recording::tick_rate(),
+        )?),
+    };
+    let mut camera_path = Vec::new();
+    for _ in 0..frames {
+        if state.is_device_lost() {
+            state = state.recover_from_device_loss().await?;
+        }
+        state.update();
+        draw_sprites(&mut state.sprites);
+        camera_path.push(CameraPose {
+            eye: state.camera.eye.into(),
+            target: state.camera.target.into(),
+        });
+        match state.render() {
+            Err(wgpu::SurfaceError::OutOfMemory) => state.mark_device_lost(),
+            result => result?,
+        }
+        if let Some(sequence) = &mut sequence {
+            let target = state.offscreen_target.as_ref().expect("Headless state renders to offscreen target");
+            sequence.write_frame(&capture::read_texture(&state.device, &state.queue, &target.texture)?)?;
+        }
+    }
+    if let Some(sequence) = sequence {
+        sequence.finish()?;
+    }
+    if state.profiler.is_enabled() {
+        log::info!("{}", state.profiler.summary());
+        state.write_trace();
+    }
+    let target = state.offscreen_target.as_ref().expect("Headless state renders to offscreen target");
+    let frame = capture::read_texture(&state.device, &state.queue, &target.texture)?;
+    Ok(HeadlessOutput { frame, camera_path })
+}
+
+// Renders frames without window and saves the last one as png when it was requested.
+async fn run_headless(scene: &Scene, options: &Options, draw_sprites: impl FnMut(&mut SpriteBatch)) -> Result<(), RendererError> {
+    let HeadlessOutput { frame, .. } = render_headless_with_sprites(scene, options, draw_sprites).await?;
+    if let Some(output) = &options.headless_output {
+        frame.save(output).map_err(RendererError::ImageWrite)?;
+        log::info!("Saved frame to {}", output.display());
+    }
+    Ok(())
+}
+
+pub async fn run(options: Options) -> Result<(), RendererError> {
+    run_with_sprites(options, |_| {}).await
+}
+
+// Like `run`, `draw_sprites` adds sprites to every frame after those of the scene.
+pub async fn run_with_sprites(options: Options, mut draw_sprites: impl FnMut(&mut SpriteBatch) + 'static) -> Result<(), RendererError> {
     env_logger::init();
+    if options.list_adapters {
+        let backends = options.backend.to_wgpu();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            dx12_shader_compiler: Default::default(),
+        });
+        adapter::print_adapters(&instance, backends);
+        return Ok(());
+    }
+
+    let scene = Scene::load(&options.scene)?;
+    log::info!("Loaded scene {}", scene.path.display());
+
+    if options.is_headless() {
+        return run_headless(&scene, &options, draw_sprites).await;
+    }
+
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut window_builder = WindowBuilder::new()
+        .with_inner_size(winit::dpi::PhysicalSize::new(options.width, options.height));
+    if options.fullscreen {
+        window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    let window = window_builder.build(&event_loop)?;
 
 
     // State::new uses async code, so we're going to wait for it to finish
-    let mut state = State::new(window).await;
+    // State is taken out of the option for a moment when it is recreated after device loss.
+    let mut state = Some(State::new(Some(window), &scene, &options).await?);
 
     event_loop.run(move |event, _, control_flow| {
+        if matches!(event, Event::RedrawRequested(_)) && state.as_ref().is_some_and(State::is_device_lost) {
+            let lost_state = state.take().expect("State is present outside of recovery");
+            match pollster::block_on(lost_state.recover_from_device_loss()) {
+                Ok(recovered) => state = Some(recovered),
+                Err(e) => {
+                    log::error!("Could not recover from device loss: {}", e);
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            }
+        }
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == state.window().id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
-                            input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        WindowEvent::KeyboardInput {
-                            input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Space),
-                                ..
-                            },
-                            ..
-                        } => state.depth_visualisation = !state.depth_visualisation,
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &&mut so w have to dereference it twice
-                            state.resize(**new_inner_size);
-                        }
-                        WindowEvent::CursorEntered { device_id: _device_id } => {
-                            state.cursor_in = true;
-                        }
-                        WindowEvent::CursorLeft { device_id: _device_id } => {
-                            state.cursor_in = false;
-                        }
-                        _ => {}
+            } if window_id == state.window().id() && !state.input(event) => {
+                match event {
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(*physical_size);
+                    }
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        // new_inner_size is &&mut so w have to dereference it twice
+                        state.resize(**new_inner_size);
                     }
+                    _ => {}
                 }
             }
             Event::RedrawRequested(window_id) if window_id == state.window().id() => {
                 state.update();
+                draw_sprites(&mut state.sprites);
                 match state.render() {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         state.resize(state.size)
                     }
-                    // The system is out of memory, we should probably quit
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                    // The system is out of memory, try to start over with new device
+                    Err(wgpu::SurfaceError::OutOfMemory) => state.mark_device_lost(),
 
                     Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
                 }
             }
             Event::RedrawEventsCleared => {
+                if state.exit_requested {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
                 state.window().request_redraw();
             }
+            Event::LoopDestroyed => state.save_recording(),
             _ => {}
         }
     });
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::Parser;
+
+    use super::*;
+
+    const DEFAULT_SCENE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/default.ron");
+    const TICKS: u64 = 30;
+
+    #[allow(deprecated)]
+    fn key(key: VirtualKeyCode, pressed: bool) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            // SAFETY: the id is only compared with other ids, input of the sandbox ignores it.
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state: if pressed { ElementState::Pressed } else { ElementState::Released },
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    fn headless_state(scene: &Scene, flag: &str, recording: &Path) -> Option<State> {
+        let options = Options::parse_from([
+            "wgpu_sandbox".as_ref(), flag.as_ref(), recording.as_os_str(),
+            "--width".as_ref(), "64".as_ref(), "--height".as_ref(), "64".as_ref(),
+        ]);
+        match pollster::block_on(State::new(None, scene, &options)) {
+            Err(RendererError::NoAdapter { .. }) => None,
+            result => Some(result.unwrap()),
+        }
+    }
+
+    // Camera pose, fovy and position of the first instance after every update.
+    fn snapshot(state: &State) -> ([f32; 3], f32, [f32; 3]) {
+        (state.camera.eye.into(), state.camera.fovy, state.instances[0].position.into())
+    }
+
+    #[test]
+    fn settings_edits_are_recorded_and_replayed() {
+        let scene = Scene::load(Path::new(DEFAULT_SCENE_PATH)).unwrap();
+        // Process id keeps concurrent test runs from other checkouts apart.
+        let recording = std::env::temp_dir().join(format!("wgpu_sandbox_settings_replay_{}.ron", std::process::id()));
+        let Some(mut state) = headless_state(&scene, "--record", &recording) else {
+            eprintln!("no adapter available, skipping settings replay");
+            return;
+        };
+        let mut recorded = Vec::new();
+        for tick in 0..TICKS {
+            match tick {
+                2 => assert!(state.input(&key(VirtualKeyCode::W, true))),
+                5 => state.edit_settings(SettingsEdit::Fovy(70.0)),
+                8 => state.edit_settings(SettingsEdit::CameraSpeed(0.5)),
+                12 => assert!(state.input(&key(VirtualKeyCode::W, false))),
+                20 => state.edit_settings(SettingsEdit::InstancePosition { index: 0, position: [1.0, 2.0, 3.0] }),
+                _ => {}
+            }
+            state.update();
+            recorded.push(snapshot(&state));
+        }
+        state.save_recording();
+        // GL backend can't have two instances at once.
+        drop(state);
+
+        let mut state = headless_state(&scene, "--replay", &recording).unwrap();
+        std::fs::remove_file(&recording).unwrap();
+        let replayed = (0..TICKS).map(|tick| {
+            // Live input is ignored during replay, except for quitting.
+            match tick {
+                3 => assert!(state.input(&key(VirtualKeyCode::S, true))),
+                25 => assert!(state.input(&key(VirtualKeyCode::Escape, true))),
+                _ => {}
+            }
+            state.update();
+            snapshot(&state)
+        }).collect::<Vec<_>>();
+
+        assert_eq!(recorded, replayed);
+        assert_eq!(replayed.last().unwrap().1, 70.0);
+        assert_eq!(replayed.last().unwrap().2, [1.0, 2.0, 3.0]);
+        assert_ne!(replayed[1].0, replayed[14].0);
+        assert!(state.exit_requested);
+    }
+}
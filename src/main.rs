@@ -1,5 +1,9 @@
-use wgpuSandbox::run;
+use clap::Parser;
+use wgpu_sandbox::options::Options;
+use wgpu_sandbox::run;
 
-fn main() {
-    pollster::block_on(run());
+// anyhow prints error together with all its causes when main returns it.
+fn main() -> anyhow::Result<()> {
+    pollster::block_on(run(Options::parse()))?;
+    Ok(())
 }
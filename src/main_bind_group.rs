@@ -1,5 +1,7 @@
 use std::num::NonZeroU32;
 
+use crate::sampler::SamplerDescription;
+
 // My understanding of bind group is that it simply contains all the data that is entering shader.
 // By using bind group we can describe what enters the shader as uniforms.
 // Buffers are bind directly in render pass (probably for some flexibility reasons).
@@ -9,69 +11,195 @@ pub fn create_main_bind_group(
     device: &wgpu::Device,
     layout: &wgpu::BindGroupLayout,
     texture_view: &wgpu::TextureView,
-    linear_sampler: &wgpu::Sampler,
-    nearest_sampler: &wgpu::Sampler
+    samplers: &[wgpu::Sampler],
+    use_binding_array: bool,
 ) -> wgpu::BindGroup {
+    let mut entries = vec![
+        // As of now, WGPU does not support binding an array of separate texture bindings for dynamic indexing within a single shader or draw call.
+        // Refer to this issue for more details: https://github.com/gpuweb/gpuweb/issues/822.
+        // This limitation means that we cannot dynamically choose among an array of separate, non-uniform textures (e.g., textures with varying sizes) in a shader.
+        //
+        // The workaround is to create a single texture that contains multiple layers, known as a texture array. This texture array corresponds to 'texture_2d_array' in WGSL.
+        // Each layer in this texture array can contain different image data, but all layers must have the same dimensions.
+        // The shader can then dynamically index these layers within a single draw call.
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(texture_view),
+        },
+    ];
+    for (index, sampler) in samplers.iter().enumerate() {
+        entries.push(wgpu::BindGroupEntry {
+            binding: sampler_binding(index),
+            resource: wgpu::BindingResource::Sampler(sampler),
+        });
+    }
+    if !use_binding_array {
+        // see `texture_bindings_source`
+        for index in 1..samplers.len() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: texture_copy_binding(samplers.len(), index),
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            });
+        }
+    }
     device.create_bind_group(
         &wgpu::BindGroupDescriptor {
             layout,
-            entries: &[
-                // As of now, WGPU does not support binding an array of separate texture bindings for dynamic indexing within a single shader or draw call.
-                // Refer to this issue for more details: https://github.com/gpuweb/gpuweb/issues/822.
-                // This limitation means that we cannot dynamically choose among an array of separate, non-uniform textures (e.g., textures with varying sizes) in a shader.
-                //
-                // The workaround is to create a single texture that contains multiple layers, known as a texture array. This texture array corresponds to 'texture_2d_array' in WGSL.
-                // Each layer in this texture array can contain different image data, but all layers must have the same dimensions.
-                // The shader can then dynamically index these layers within a single draw call.
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(linear_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(nearest_sampler),
-                }
-            ],
+            entries: &entries,
             label: Some("main_bind_group"),
         }
     )
 }
 
-pub fn create_main_bind_group_layout(device: &wgpu::Device, number_of_textures: u32) -> wgpu::BindGroupLayout {
-    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Texture {
-                    // It is another thing that informs GPU about data layout, I think.
-                    view_dimension: wgpu::TextureViewDimension::D2Array,
-                    multisampled: false,
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true }
-                },
-                count: Some(NonZeroU32::try_from(number_of_textures).unwrap()),
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                // This should match the filterable field of the
-                // corresponding Texture entry above.
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 2,
+// Samplers follow the texture array.
+fn sampler_binding(sampler_index: usize) -> u32 {
+    1 + sampler_index as u32
+}
+
+// Copies of the texture array for all samplers but the first one follow the samplers.
+fn texture_copy_binding(sampler_count: usize, sampler_index: usize) -> u32 {
+    (sampler_count + sampler_index) as u32
+}
+
+// Those features features are required if we want to bind storage array (texture array) as uniform.
+pub const BINDING_ARRAY_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+    .union(wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY);
+
+pub fn binding_arrays_supported(features: wgpu::Features) -> bool {
+    features.contains(BINDING_ARRAY_FEATURES)
+}
+
+/**
+    Layout of textures and samplers used by main shader.
+
+    When adapter supports binding arrays texture is bound with `count` set to number of textures.
+    Many adapters (software and GL ones in particular) do not support them, in that case texture
+    array is bound as a single `texture_2d_array` binding without `count` (plus its copy for every
+    other sampler, see `texture_bindings_source`). `create_main_shader` generates bindings matching the layout.
+*/
+pub fn create_main_bind_group_layout(
+    device: &wgpu::Device,
+    number_of_textures: u32,
+    sampler_count: usize,
+    use_binding_array: bool,
+) -> wgpu::BindGroupLayout {
+    let count = if use_binding_array {
+        Some(NonZeroU32::try_from(number_of_textures).unwrap())
+    } else {
+        None
+    };
+    let texture_type = wgpu::BindingType::Texture {
+        // It is another thing that informs GPU about data layout, I think.
+        view_dimension: wgpu::TextureViewDimension::D2Array,
+        multisampled: false,
+        sample_type: wgpu::TextureSampleType::Float { filterable: true }
+    };
+    let mut entries = vec![
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: texture_type,
+            count,
+        },
+    ];
+    for index in 0..sampler_count {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: sampler_binding(index),
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            // This should match the filterable field of the
+            // corresponding Texture entry above.
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        });
+    }
+    if !use_binding_array {
+        // The same texture bound once more for every other sampler.
+        for index in 1..sampler_count {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: texture_copy_binding(sampler_count, index),
                 visibility: wgpu::ShaderStages::FRAGMENT,
-                // This should match the filterable field of the
-                // corresponding Texture entry above.
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                ty: texture_type,
                 count: None,
-            }
-        ],
+            });
+        }
+    }
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &entries,
         label: Some("texture_bind_group_layout"),
     })
-}
\ No newline at end of file
+}
+
+/**
+    Main shader is put together from shader.wgsl and texture bindings matching layout
+    created by `create_main_bind_group_layout`.
+*/
+pub fn create_main_shader(device: &wgpu::Device, samplers: &[SamplerDescription], use_binding_array: bool) -> wgpu::ShaderModule {
+    create_textured_shader(device, "Main Shader", include_str!("shaders/shader.wgsl"), samplers, use_binding_array)
+}
+
+// Sprites are sampled from the same bind group as instances, see sprite.rs.
+pub fn create_sprite_shader(device: &wgpu::Device, samplers: &[SamplerDescription], use_binding_array: bool) -> wgpu::ShaderModule {
+    create_textured_shader(device, "Sprite Shader", include_str!("shaders/sprite.wgsl"), samplers, use_binding_array)
+}
+
+fn create_textured_shader(
+    device: &wgpu::Device,
+    label: &str,
+    shader_source: &str,
+    samplers: &[SamplerDescription],
+    use_binding_array: bool,
+) -> wgpu::ShaderModule {
+    let source = format!("{}\n{}", texture_bindings_source(samplers, use_binding_array), shader_source);
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+/**
+    WGSL source of texture and sampler bindings together with `sample_texture` function
+    which samples texture array with sampler picked by index.
+
+    Some backends (GL) combine texture and sampler into one object so a texture can be used
+    with only one sampler. Because of that, without binding arrays, the same texture array
+    is bound once for each sampler.
+
+    LOD bias is applied by scaling texture coordinate derivatives passed to `textureSampleGrad`.
+*/
+fn texture_bindings_source(samplers: &[SamplerDescription], use_binding_array: bool) -> String {
+    let mut source = String::from("@group(0) @binding(0)\nvar my_textures: texture_2d_array<f32>;\n");
+    for index in 0..samplers.len() {
+        source += &format!("@group(0) @binding({})\nvar sampler_{}: sampler;\n", sampler_binding(index), index);
+    }
+    let texture_name = |index: usize| {
+        if use_binding_array || index == 0 {
+            "my_textures".to_string()
+        } else {
+            format!("my_textures_{}", index)
+        }
+    };
+    if !use_binding_array {
+        for index in 1..samplers.len() {
+            source += &format!(
+                "@group(0) @binding({})\nvar {}: texture_2d_array<f32>;\n",
+                texture_copy_binding(samplers.len(), index), texture_name(index),
+            );
+        }
+    }
+    source += "\nfn sample_texture(tex_coords: vec2<f32>, texture_index: i32, sampler_index: i32, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {\n";
+    source += "    var colour: vec4<f32>;\n    switch sampler_index {\n";
+    for (index, sampler) in samplers.iter().enumerate() {
+        // Out of range indices use the last sampler.
+        let selector = if index + 1 == samplers.len() {
+            format!("{}, default", index)
+        } else {
+            index.to_string()
+        };
+        source += &format!(
+            "        case {}: {{\n            colour = textureSampleGrad({}, sampler_{}, tex_coords, texture_index, ddx * {:?}, ddy * {:?});\n        }}\n",
+            selector, texture_name(index), index, sampler.gradient_scale(), sampler.gradient_scale(),
+        );
+    }
+    source += "    }\n    return colour;\n}\n";
+    source
+}
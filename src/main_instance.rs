@@ -1,8 +1,24 @@
+use std::ops::Range;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix3, Point3, Rad, Vector2};
+use serde::{Deserialize, Serialize};
+
+use crate::animation::FlipbookAnimation;
+use crate::blend_mode::BlendMode;
+use crate::mesh::MeshRange;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct MainInstance {
     pub position: cgmath::Vector3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
-    pub use_linear_sampler: bool,
-    pub texture_index: i32
+    // Index of sampler from scene samplers (see `sampler::SamplerDescription`).
+    pub sampler_index: i32,
+    pub texture_index: i32,
+    pub uv_transform: UvTransform,
+    pub animation: Option<FlipbookAnimation>,
+    pub blend_mode: BlendMode,
+    // Index of mesh (see `mesh::MeshBuffers`) drawn for this instance, it is not sent to the shader.
+    pub mesh_index: usize,
 }
 
 
@@ -10,24 +26,200 @@ impl MainInstance {
     pub fn to_raw(&self) -> MainInstanceRaw {
         MainInstanceRaw {
             model: (cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation)).into(),
-            use_linear_sampler: {
-                if self.use_linear_sampler {
-                    1
-                } else {
-                    0
-                }
-            },
-            texture_index: self.texture_index
+            sampler_index: self.sampler_index,
+            texture_index: self.texture_index,
+            uv_transform: self.uv_transform.to_raw_scrolling(),
+            animation_frames: FlipbookAnimation::to_raw_frames(self.animation.as_ref()),
+            animation_timing: FlipbookAnimation::to_raw_timing(self.animation.as_ref()),
+            alpha_cutoff: self.blend_mode.alpha_cutoff(),
+        }
+    }
+}
+
+/**
+    Transformation of mesh texture coordinates. They are scaled first, then rotated around
+    the middle of scaled area and moved by `offset`. For example a frame of sprite sheet with
+    4 columns and 2 rows is `offset: (column / 4, row / 2), scale: (0.25, 0.5)`, while
+    `scale: (8, 8)` with a repeat sampler tiles texture 8 times across the mesh.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UvTransform {
+    pub offset: [f32; 2],
+    pub scale: [f32; 2],
+    pub rotation_degrees: f32,
+    // Change of offset per second, for scrolling textures. Offset itself stays as written, the shader
+    // adds scroll multiplied by time to it.
+    pub scroll: [f32; 2],
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: [0.0, 0.0],
+            scale: [1.0, 1.0],
+            rotation_degrees: 0.0,
+            scroll: [0.0, 0.0],
+        }
+    }
+}
+
+impl UvTransform {
+    // Rows of 2x3 matrix applied to (u, v, 1).
+    pub fn to_raw(self) -> [[f32; 3]; 2] {
+        let scale = Vector2::from(self.scale);
+        let half_scale = scale * 0.5;
+        let matrix = Matrix3::from_translation(Vector2::from(self.offset) + half_scale)
+            * Matrix3::from_angle_z(Rad::from(cgmath::Deg(self.rotation_degrees)))
+            * Matrix3::from_translation(-half_scale)
+            * Matrix3::from_nonuniform_scale(scale.x, scale.y);
+        // cgmath matrices are column major.
+        [
+            [matrix.x.x, matrix.y.x, matrix.z.x],
+            [matrix.x.y, matrix.y.y, matrix.z.y],
+        ]
+    }
+
+    // Rows of the matrix with scroll speed of u and v as their last value.
+    pub fn to_raw_scrolling(self) -> [[f32; 4]; 2] {
+        let [row_0, row_1] = self.to_raw();
+        [
+            [row_0[0], row_0[1], row_0[2], self.scroll[0]],
+            [row_1[0], row_1[1], row_1[2], self.scroll[1]],
+        ]
+    }
+}
+
+/**
+    Converts opaque (and alpha tested) instances to data of instance buffer, transparent ones
+    go to `TransparentInstances` instead.
+    Instances are grouped by mesh so that every mesh can be drawn with a single draw call,
+    returned ranges tell which part of the buffer belongs to which mesh.
+*/
+pub fn build_instance_data(instances: &[MainInstance], mesh_count: usize) -> (Vec<MainInstanceRaw>, Vec<Range<u32>>) {
+    let mut data = Vec::with_capacity(instances.len());
+    let mut ranges = Vec::with_capacity(mesh_count);
+    for mesh_index in 0..mesh_count {
+        let start = data.len() as u32;
+        data.extend(
+            instances.iter()
+                .filter(|i| i.mesh_index == mesh_index && !i.blend_mode.is_transparent())
+                .map(MainInstance::to_raw)
+        );
+        ranges.push(start..data.len() as u32);
+    }
+    (data, ranges)
+}
+
+// Consecutive sorted transparent instances sharing mesh and blend mode, drawn with one draw call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransparentDraw {
+    pub mesh_index: usize,
+    pub blend_mode: BlendMode,
+    pub instances: Range<u32>,
+}
+
+/**
+    Instances with transparent blend modes, they are sorted back to front by distance of
+    the middle of their mesh from the camera and uploaded again every frame.
+*/
+pub struct TransparentInstances {
+    buffer: wgpu::Buffer,
+    // Number of instances the buffer can hold.
+    capacity: u64,
+    pub draws: Vec<TransparentDraw>,
+}
+
+impl TransparentInstances {
+    const INITIAL_CAPACITY: u64 = 64;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            buffer: Self::create_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            draws: Vec::new(),
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Transparent Instance Buffer"),
+            size: capacity * std::mem::size_of::<MainInstanceRaw>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instances: &[MainInstance],
+        meshes: &[MeshRange],
+        eye: Point3<f32>,
+    ) {
+        let (sorted, draws) = sort_transparent(instances, meshes, eye);
+        self.draws = draws;
+
+        let needed = sorted.len() as u64;
+        if needed > self.capacity {
+            self.capacity = needed.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        if !sorted.is_empty() {
+            let data = sorted.iter().map(|instance| instance.to_raw()).collect::<Vec<_>>();
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&data));
         }
     }
 }
 
+// Transparent instances from the furthest one to the nearest one, together with draws of them.
+fn sort_transparent<'a>(
+    instances: &'a [MainInstance],
+    meshes: &[MeshRange],
+    eye: Point3<f32>,
+) -> (Vec<&'a MainInstance>, Vec<TransparentDraw>) {
+    let mut sorted = instances.iter()
+        .filter(|instance| instance.blend_mode.is_transparent())
+        .map(|instance| {
+            let (min, max) = meshes[instance.mesh_index].bounds;
+            let middle = instance.position + instance.rotation * min.midpoint(max).to_vec();
+            ((Point3::from_vec(middle) - eye).magnitude2(), instance)
+        })
+        .collect::<Vec<_>>();
+    sorted.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let mut draws: Vec<TransparentDraw> = Vec::new();
+    for (index, (_, instance)) in sorted.iter().enumerate() {
+        let index = index as u32;
+        match draws.last_mut() {
+            Some(draw) if draw.mesh_index == instance.mesh_index && draw.blend_mode == instance.blend_mode => {
+                draw.instances.end = index + 1;
+            }
+            _ => draws.push(TransparentDraw {
+                mesh_index: instance.mesh_index,
+                blend_mode: instance.blend_mode,
+                instances: index..index + 1,
+            }),
+        }
+    }
+    (sorted.into_iter().map(|(_, instance)| instance).collect(), draws)
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct MainInstanceRaw {
     pub model: [[f32; 4]; 4],
-    pub use_linear_sampler: i32,
-    pub texture_index: i32
+    pub sampler_index: i32,
+    pub texture_index: i32,
+    pub uv_transform: [[f32; 4]; 2],
+    pub animation_frames: [i32; 3],
+    pub animation_timing: [f32; 2],
+    pub alpha_cutoff: f32,
 }
 
 impl MainInstanceRaw {
@@ -76,7 +268,97 @@ impl MainInstanceRaw {
                     shader_location: 10,
                     format: wgpu::VertexFormat::Sint32,
                 },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 16]>() + mem::size_of::<[i32; 2]>()) as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 20]>() + mem::size_of::<[i32; 2]>()) as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 24]>() + mem::size_of::<[i32; 2]>()) as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Sint32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 24]>() + mem::size_of::<[i32; 5]>()) as wgpu::BufferAddress,
+                    shader_location: 14,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 26]>() + mem::size_of::<[i32; 5]>()) as wgpu::BufferAddress,
+                    shader_location: 15,
+                    format: wgpu::VertexFormat::Float32,
+                },
             ],
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Quaternion, Vector3};
+
+    use super::*;
+
+    fn instance(z: f32, mesh_index: usize, blend_mode: BlendMode) -> MainInstance {
+        MainInstance {
+            position: Vector3::new(0.0, 0.0, z),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            sampler_index: 0,
+            texture_index: z as i32,
+            uv_transform: UvTransform::default(),
+            animation: None,
+            blend_mode,
+            mesh_index,
+        }
+    }
+
+    fn mesh(min: [f32; 3], max: [f32; 3]) -> MeshRange {
+        MeshRange { base_vertex: 0, indices: 0..6, bounds: (Point3::from(min), Point3::from(max)) }
+    }
+
+    #[test]
+    fn transparent_instances_are_sorted_back_to_front() {
+        let instances = [
+            instance(-1.0, 0, BlendMode::AlphaBlend),
+            instance(-5.0, 0, BlendMode::Opaque),
+            instance(-3.0, 0, BlendMode::Additive),
+            instance(-4.0, 0, BlendMode::AlphaBlend),
+            instance(-6.0, 0, BlendMode::AlphaTest { cutoff: 0.5 }),
+            instance(-2.0, 1, BlendMode::AlphaBlend),
+            instance(-7.0, 0, BlendMode::AlphaBlend),
+        ];
+        // Middle of the second mesh is 4 units further, so its instance sorts as if it was at -6.
+        let meshes = [mesh([0.0; 3], [1.0, 1.0, 0.0]), mesh([0.0, 0.0, -5.0], [1.0, 1.0, -3.0])];
+        let (sorted, draws) = sort_transparent(&instances, &meshes, Point3::new(0.0, 0.0, 0.0));
+
+        let depths = sorted.iter().map(|instance| instance.position.z).collect::<Vec<_>>();
+        assert_eq!(depths, [-7.0, -2.0, -4.0, -3.0, -1.0]);
+        assert_eq!(draws, [
+            TransparentDraw { mesh_index: 0, blend_mode: BlendMode::AlphaBlend, instances: 0..1 },
+            TransparentDraw { mesh_index: 1, blend_mode: BlendMode::AlphaBlend, instances: 1..2 },
+            TransparentDraw { mesh_index: 0, blend_mode: BlendMode::AlphaBlend, instances: 2..3 },
+            TransparentDraw { mesh_index: 0, blend_mode: BlendMode::Additive, instances: 3..4 },
+            TransparentDraw { mesh_index: 0, blend_mode: BlendMode::AlphaBlend, instances: 4..5 },
+        ]);
+    }
+
+    #[test]
+    fn neighbouring_instances_share_draws() {
+        let instances = [
+            instance(-3.0, 0, BlendMode::Additive),
+            instance(-1.0, 0, BlendMode::Premultiplied),
+            instance(-4.0, 0, BlendMode::Additive),
+            instance(-2.0, 0, BlendMode::Premultiplied),
+        ];
+        let (_, draws) = sort_transparent(&instances, &[mesh([0.0; 3], [1.0; 3])], Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(draws, [
+            TransparentDraw { mesh_index: 0, blend_mode: BlendMode::Additive, instances: 0..2 },
+            TransparentDraw { mesh_index: 0, blend_mode: BlendMode::Premultiplied, instances: 2..4 },
+        ]);
+    }
+}
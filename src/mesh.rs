@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use wgpu::util::DeviceExt;
+
+use crate::vertex::Vertex;
+
+pub struct MeshRange {
+    pub base_vertex: i32,
+    pub indices: Range<u32>,
+    // Corners of box containing all vertices of the mesh.
+    pub bounds: (cgmath::Point3<f32>, cgmath::Point3<f32>),
+}
+
+/**
+    All meshes of the scene packed into one vertex and one index buffer.
+    Each mesh is drawn by picking its index range and offsetting indices by its base vertex.
+
+    Meshes are also kept without indices, where every triangle has its own three vertices.
+    That is what wireframe drawn by shader uses as it needs to know which corner of a triangle
+    a vertex is (see `render_mode`).
+*/
+pub struct MeshBuffers {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub ranges: Vec<MeshRange>,
+    pub unindexed_vertex_buffer: wgpu::Buffer,
+    pub unindexed_ranges: Vec<Range<u32>>,
+}
+
+impl MeshBuffers {
+    pub fn new(device: &wgpu::Device, meshes: &[(Vec<Vertex>, Vec<u16>)]) -> Self {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut ranges = Vec::with_capacity(meshes.len());
+        let mut unindexed_vertices = Vec::new();
+        let mut unindexed_ranges = Vec::with_capacity(meshes.len());
+        for (mesh_vertices, mesh_indices) in meshes {
+            let first_vertex = unindexed_vertices.len() as u32;
+            unindexed_vertices.extend(mesh_indices.iter().map(|i| mesh_vertices[*i as usize]));
+            unindexed_ranges.push(first_vertex..unindexed_vertices.len() as u32);
+
+            let first_index = indices.len() as u32;
+            ranges.push(MeshRange {
+                base_vertex: vertices.len() as i32,
+                indices: first_index..first_index + mesh_indices.len() as u32,
+                bounds: Self::bounds(mesh_vertices),
+            });
+            vertices.extend_from_slice(mesh_vertices);
+            indices.extend_from_slice(mesh_indices);
+        }
+        // Index buffer size has to be multiple of 4 bytes (COPY_BUFFER_ALIGNMENT)
+        if indices.len() % 2 != 0 {
+            indices.push(0);
+        }
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        let unindexed_vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Unindexed Vertex Buffer"),
+                contents: bytemuck::cast_slice(&unindexed_vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            ranges,
+            unindexed_vertex_buffer,
+            unindexed_ranges,
+        }
+    }
+
+    // Unindexed buffer is used by wireframe drawn by shader, see `MainPipelines::needs_unindexed_meshes`.
+    pub fn set_buffers<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, unindexed: bool) {
+        if unindexed {
+            render_pass.set_vertex_buffer(0, self.unindexed_vertex_buffer.slice(..));
+        } else {
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        }
+    }
+
+    // Draws instances of the mesh, buffers have to be set by `set_buffers` first.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, mesh_index: usize, instances: Range<u32>, unindexed: bool) {
+        if unindexed {
+            render_pass.draw(self.unindexed_ranges[mesh_index].clone(), instances);
+        } else {
+            let mesh = &self.ranges[mesh_index];
+            render_pass.draw_indexed(mesh.indices.clone(), mesh.base_vertex, instances);
+        }
+    }
+
+    fn bounds(vertices: &[Vertex]) -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for vertex in vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+        }
+        (min.into(), max.into())
+    }
+}
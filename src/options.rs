@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use crate::adapter::AdapterSelector;
+
+// Scene loaded when no other scene is requested.
+const DEFAULT_SCENE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/default.ron");
+
+/**
+    Command line options of the sandbox.
+*/
+#[derive(Debug, Clone, Parser)]
+#[command(version, about = "wgpu sandbox renderer")]
+pub struct Options {
+    /// Scene file to load
+    #[arg(long, default_value = DEFAULT_SCENE_PATH)]
+    pub scene: PathBuf,
+
+    /// Key and mouse bindings of actions, actions missing in the file keep default bindings of config/input.ron
+    #[arg(long)]
+    pub input_config: Option<PathBuf>,
+
+    /// Width of the window (or of the rendered image in headless mode)
+    #[arg(long, default_value_t = 800)]
+    pub width: u32,
+
+    /// Height of the window (or of the rendered image in headless mode)
+    #[arg(long, default_value_t = 600)]
+    pub height: u32,
+
+    /// Start in borderless fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Presentation mode of the surface, falls back to fifo when not supported
+    #[arg(long, value_enum, default_value_t = PresentModeArg::Fifo)]
+    pub present_mode: PresentModeArg,
+
+    /// Graphics backend used to look for adapters
+    #[arg(long, value_enum, default_value_t = BackendArg::All)]
+    pub backend: BackendArg,
+
+    /// Number of samples used for multisample anti-aliasing (1 disables it)
+    #[arg(long, default_value_t = 1, value_parser = parse_sample_count)]
+    pub msaa: u32,
+
+    /// Adapter to use, either index from --list-adapters or part of its name
+    #[arg(long)]
+    pub adapter: Option<AdapterSelector>,
+
+    /// Power preference used to pick adapter when no adapter is selected explicitly
+    #[arg(long, value_enum, default_value_t = PowerPreferenceArg::Default)]
+    pub power_preference: PowerPreferenceArg,
+
+    /// Render without a window and write the last frame as png to this path
+    #[arg(long)]
+    pub headless_output: Option<PathBuf>,
+
+    /// Render without a window and write every frame as numbered png to this directory
+    #[arg(long)]
+    pub sequence_output: Option<PathBuf>,
+
+    /// Render without a window and write every frame to this uncompressed Y4M video
+    #[arg(long)]
+    pub y4m_output: Option<PathBuf>,
+
+    /// Number of frames rendered in headless mode
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: u32,
+
+    /// Camera path to play from the start
+    #[arg(long)]
+    pub camera_path: Option<PathBuf>,
+
+    /// Where camera path is saved (Ctrl+K by default)
+    #[arg(long, default_value = "camera_path.ron")]
+    pub camera_path_output: PathBuf,
+
+    /// Record input to this file, it is written when the window is closed
+    #[arg(long, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Replay input recorded with --record, live input is ignored until the replay ends
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Start with profiler enabled (it can be toggled with P key)
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Where Chrome trace is written when profiler is turned off (or when headless rendering ends)
+    #[arg(long, default_value = "trace.json")]
+    pub trace_output: PathBuf,
+
+    /// Print available adapters and exit
+    #[arg(long)]
+    pub list_adapters: bool,
+}
+
+impl Options {
+    // Whether frames are rendered without window, written to files instead.
+    pub fn is_headless(&self) -> bool {
+        self.headless_output.is_some() || self.sequence_output.is_some() || self.y4m_output.is_some()
+    }
+}
+
+fn parse_sample_count(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(count @ (1 | 2 | 4 | 8)) => Ok(count),
+        _ => Err("sample count has to be one of 1, 2, 4, 8".into()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PresentModeArg {
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentModeArg {
+    pub fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            PresentModeArg::Fifo => wgpu::PresentMode::Fifo,
+            PresentModeArg::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentModeArg::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PowerPreferenceArg {
+    Default,
+    Low,
+    High,
+}
+
+impl PowerPreferenceArg {
+    pub fn to_wgpu(self) -> wgpu::PowerPreference {
+        match self {
+            PowerPreferenceArg::Default => wgpu::PowerPreference::default(),
+            PowerPreferenceArg::Low => wgpu::PowerPreference::LowPower,
+            PowerPreferenceArg::High => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackendArg {
+    All,
+    Vulkan,
+    Gl,
+    Dx12,
+    Metal,
+}
+
+impl BackendArg {
+    pub fn to_wgpu(self) -> wgpu::Backends {
+        match self {
+            BackendArg::All => wgpu::Backends::all(),
+            BackendArg::Vulkan => wgpu::Backends::VULKAN,
+            BackendArg::Gl => wgpu::Backends::GL,
+            BackendArg::Dx12 => wgpu::Backends::DX12,
+            BackendArg::Metal => wgpu::Backends::METAL,
+        }
+    }
+}
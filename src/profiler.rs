@@ -0,0 +1,439 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Number of frames kept in history.
+const HISTORY_LENGTH: usize = 240;
+// How often averages are logged while profiler is enabled.
+const LOG_INTERVAL: Duration = Duration::from_secs(2);
+// Every pass needs two timestamps, passes over this limit are not measured.
+const MAX_GPU_PASSES: u32 = 16;
+// Trace keeps only the latest events, a few minutes of frames, so long sessions don't use ever more memory.
+const MAX_TRACE_EVENTS: usize = 100_000;
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    // Time since start of previous frame.
+    pub frame_time: Duration,
+    pub update_time: Duration,
+    pub render_time: Duration,
+    pub draw_calls: u32,
+    pub instances: u32,
+    // Time GPU spent in every pass of the frame, only measured with TIMESTAMP_QUERY.
+    pub gpu_passes: Vec<(&'static str, Duration)>,
+}
+
+struct TraceEvent {
+    name: &'static str,
+    // Chrome trace shows CPU and GPU events in separate rows (threads).
+    thread: u32,
+    start: Duration,
+    duration: Duration,
+}
+
+/**
+    Measures GPU time of passes with timestamps written to command encoders before and after each pass.
+    Results are read back at the end of the frame, which waits for GPU to finish the frame,
+    so it is only done while profiler is enabled.
+*/
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    // Nanoseconds per timestamp tick.
+    period: f32,
+    passes: Vec<&'static str>,
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let count = MAX_GPU_PASSES * 2;
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+            passes: Vec::new(),
+        }
+    }
+
+    fn begin_pass(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str) -> bool {
+        if self.passes.len() as u32 >= MAX_GPU_PASSES {
+            return false;
+        }
+        encoder.write_timestamp(&self.query_set, self.passes.len() as u32 * 2);
+        self.passes.push(name);
+        true
+    }
+
+    fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, self.passes.len() as u32 * 2 - 1);
+    }
+
+    fn read(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<(&'static str, Duration)> {
+        if self.passes.is_empty() {
+            return Vec::new();
+        }
+        let count = self.passes.len() as u32 * 2;
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Timestamp Resolve Encoder"),
+        });
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = self.readback_buffer.slice(..size);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        let passes = std::mem::take(&mut self.passes);
+        // Frame without GPU timings is better than wrong ones.
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                log::warn!("Could not read pass timestamps, GPU times of the frame are skipped: {}", e);
+                return Vec::new();
+            }
+            Err(e) => {
+                log::warn!("Pass timestamps were not mapped, GPU times of the frame are skipped: {}", e);
+                return Vec::new();
+            }
+        }
+        let timestamps: Vec<u64> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        self.readback_buffer.unmap();
+
+        let period = self.period as f64;
+        passes.into_iter()
+            .zip(timestamps.chunks_exact(2))
+            .map(|(name, pair)| {
+                let nanos = pair[1].saturating_sub(pair[0]) as f64 * period;
+                (name, Duration::from_nanos(nanos as u64))
+            })
+            .collect()
+    }
+}
+
+/**
+    Collects statistics of frames: CPU time of the whole frame, of `State::update` and `State::render`,
+    number of draw calls and drawn instances and (when adapter supports TIMESTAMP_QUERY)
+    GPU time of every pass.
+
+    Frame times are always kept in rolling history. GPU timing, periodic logging and recording
+    of Chrome trace (see `write_chrome_trace`) only happen while profiler is enabled.
+*/
+pub struct Profiler {
+    enabled: bool,
+    gpu: Option<GpuTimer>,
+    history: VecDeque<FrameStats>,
+    current: FrameStats,
+    frame_start: Option<Instant>,
+    // Time origin of trace events.
+    trace_start: Instant,
+    trace: VecDeque<TraceEvent>,
+    last_log: Instant,
+}
+
+// Disabled profiler without GPU timer.
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gpu: None,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            current: FrameStats::default(),
+            frame_start: None,
+            trace_start: Instant::now(),
+            trace: VecDeque::new(),
+            last_log: Instant::now(),
+        }
+    }
+}
+
+impl Profiler {
+    pub const TIMESTAMP_FEATURES: wgpu::Features = wgpu::Features::TIMESTAMP_QUERY;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, enabled: bool) -> Self {
+        let mut profiler = Self {
+            enabled,
+            ..Default::default()
+        };
+        profiler.set_device(device, queue);
+        profiler
+    }
+
+    /**
+        Creates GPU timer for `device`. History, trace and enabled state are kept,
+        so the profiler goes on after device was lost and recreated.
+    */
+    pub fn set_device(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.gpu = device.features().contains(Self::TIMESTAMP_FEATURES)
+            .then(|| GpuTimer::new(device, queue));
+        if self.gpu.is_none() {
+            log::info!("Adapter does not support timestamp queries, GPU time of passes is not measured");
+        }
+    }
+
+    // Drops GPU timer of lost device, GPU time of passes is not measured until `set_device`.
+    pub fn lose_device(&mut self) {
+        self.gpu = None;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /**
+        Enabling profiler starts new trace, the one recorded so far is dropped.
+    */
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.trace.clear();
+            self.trace_start = Instant::now();
+            self.last_log = Instant::now();
+        }
+        self.enabled = enabled;
+    }
+
+    // Called at the start of `State::update`, frame time is measured between these calls.
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        self.current = FrameStats {
+            frame_time: self.frame_start.map(|start| now - start).unwrap_or_default(),
+            ..Default::default()
+        };
+        self.frame_start = Some(now);
+    }
+
+    pub fn record_update(&mut self, start: Instant) {
+        self.current.update_time = start.elapsed();
+        self.record_trace_event("update", 0, start, self.current.update_time);
+    }
+
+    pub fn record_draw(&mut self, instances: u32) {
+        self.record_draws(1, instances);
+    }
+
+    pub fn record_draws(&mut self, draw_calls: u32, instances: u32) {
+        self.current.draw_calls += draw_calls;
+        self.current.instances += instances;
+    }
+
+    pub fn begin_pass(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str) -> PassTimestamp {
+        match &mut self.gpu {
+            Some(gpu) if self.enabled => PassTimestamp(gpu.begin_pass(encoder, name)),
+            _ => PassTimestamp(false),
+        }
+    }
+
+    pub fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder, pass: PassTimestamp) {
+        if let (Some(gpu), PassTimestamp(true)) = (&mut self.gpu, pass) {
+            gpu.end_pass(encoder);
+        }
+    }
+
+    /**
+        Called at the end of `State::render` with time when rendering started.
+        Reads GPU timestamps of the frame and moves its statistics to history.
+    */
+    pub fn end_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, render_start: Instant) {
+        self.current.render_time = render_start.elapsed();
+        self.record_trace_event("render", 0, render_start, self.current.render_time);
+        if let Some(frame_start) = self.frame_start {
+            self.record_trace_event("frame", 0, frame_start, frame_start.elapsed());
+        }
+        if let (Some(gpu), true) = (&mut self.gpu, self.enabled) {
+            self.current.gpu_passes = gpu.read(device, queue);
+            // GPU clock can't be matched with CPU one, passes are shown as if GPU started with rendering.
+            let mut gpu_start = render_start;
+            for (name, duration) in self.current.gpu_passes.clone() {
+                self.record_trace_event(name, 1, gpu_start, duration);
+                gpu_start += duration;
+            }
+        }
+
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(std::mem::take(&mut self.current));
+
+        if self.enabled && self.last_log.elapsed() >= LOG_INTERVAL {
+            self.last_log = Instant::now();
+            log::info!("{}", self.summary());
+        }
+    }
+
+    fn record_trace_event(&mut self, name: &'static str, thread: u32, start: Instant, duration: Duration) {
+        if self.enabled {
+            if self.trace.len() == MAX_TRACE_EVENTS {
+                self.trace.pop_front();
+            }
+            self.trace.push_back(TraceEvent {
+                name,
+                thread,
+                start: start.saturating_duration_since(self.trace_start),
+                duration,
+            });
+        }
+    }
+
+    pub fn history(&self) -> impl Iterator<Item=&FrameStats> {
+        self.history.iter()
+    }
+
+    pub fn average_frame_time(&self) -> Duration {
+        Self::average(self.history.iter().map(|f| f.frame_time), self.history.len())
+    }
+
+    fn average(durations: impl Iterator<Item=Duration>, count: usize) -> Duration {
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        durations.sum::<Duration>() / count as u32
+    }
+
+    /**
+        Averages over history in one line, the same thing that is logged periodically.
+    */
+    pub fn summary(&self) -> String {
+        let count = self.history.len();
+        let Some(last) = self.history.back() else {
+            return "no frames recorded".into();
+        };
+        let mut summary = format!(
+            "frame {:.2} ms (update {:.2} ms, render {:.2} ms), {} draw calls, {} instances",
+            self.average_frame_time().as_secs_f64() * 1000.0,
+            Self::average(self.history().map(|f| f.update_time), count).as_secs_f64() * 1000.0,
+            Self::average(self.history().map(|f| f.render_time), count).as_secs_f64() * 1000.0,
+            last.draw_calls,
+            last.instances,
+        );
+        for (name, _) in &last.gpu_passes {
+            let pass_times = self.history()
+                .flat_map(|f| f.gpu_passes.iter().filter(|(n, _)| n == name).map(|(_, d)| *d))
+                .collect::<Vec<_>>();
+            let average = Self::average(pass_times.iter().copied(), pass_times.len());
+            let _ = write!(summary, ", GPU {} {:.3} ms", name, average.as_secs_f64() * 1000.0);
+        }
+        summary
+    }
+
+    /**
+        Writes events recorded since profiler was enabled (up to `MAX_TRACE_EVENTS` latest ones)
+        in Chrome trace format, it can be opened in chrome://tracing or https://ui.perfetto.dev.
+    */
+    pub fn write_chrome_trace(&self, path: &Path) -> std::io::Result<()> {
+        let mut json = String::from("{\"traceEvents\":[\n");
+        json.push_str("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":0,\"args\":{\"name\":\"CPU\"}},\n");
+        json.push_str("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":1,\"args\":{\"name\":\"GPU\"}}");
+        for event in &self.trace {
+            let _ = write!(
+                json,
+                ",\n{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":0,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}}}",
+                event.name,
+                event.thread,
+                event.start.as_secs_f64() * 1_000_000.0,
+                event.duration.as_secs_f64() * 1_000_000.0,
+            );
+        }
+        json.push_str("\n]}\n");
+        std::fs::write(path, json)
+    }
+}
+
+/**
+    Tells `Profiler::end_pass` whether timestamp was written at the beginning of the pass.
+*/
+#[must_use]
+pub struct PassTimestamp(bool);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(milliseconds: u64, gpu_passes: Vec<(&'static str, Duration)>) -> FrameStats {
+        FrameStats {
+            frame_time: Duration::from_millis(milliseconds),
+            update_time: Duration::from_millis(1),
+            render_time: Duration::from_millis(milliseconds / 2),
+            draw_calls: 3,
+            instances: milliseconds as u32,
+            gpu_passes,
+        }
+    }
+
+    #[test]
+    fn average_divides_sum_by_count() {
+        assert_eq!(Profiler::average(std::iter::empty(), 0), Duration::ZERO);
+        let durations = [1, 2, 6].map(Duration::from_millis);
+        assert_eq!(Profiler::average(durations.into_iter(), 3), Duration::from_millis(3));
+    }
+
+    #[test]
+    fn summary_averages_history_and_reports_last_frame() {
+        let mut profiler = Profiler::default();
+        assert_eq!(profiler.summary(), "no frames recorded");
+        profiler.history.push_back(frame(10, vec![("main", Duration::from_micros(500))]));
+        profiler.history.push_back(frame(20, vec![("main", Duration::from_micros(1500)), ("ui", Duration::from_micros(250))]));
+        assert_eq!(profiler.average_frame_time(), Duration::from_millis(15));
+        assert_eq!(
+            profiler.summary(),
+            "frame 15.00 ms (update 1.00 ms, render 7.50 ms), 3 draw calls, 20 instances, GPU main 1.000 ms, GPU ui 0.250 ms",
+        );
+    }
+
+    #[test]
+    fn chrome_trace_has_cpu_and_gpu_events() {
+        let mut profiler = Profiler::default();
+        profiler.set_enabled(true);
+        let start = profiler.trace_start;
+        profiler.record_trace_event("update", 0, start + Duration::from_micros(10), Duration::from_micros(20));
+        profiler.record_trace_event("main", 1, start + Duration::from_micros(40), Duration::from_nanos(1500));
+
+        let path = std::env::temp_dir().join(format!("wgpu_sandbox_trace_{}.json", std::process::id()));
+        profiler.write_chrome_trace(&path).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(json, concat!(
+            "{\"traceEvents\":[\n",
+            "{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":0,\"args\":{\"name\":\"CPU\"}},\n",
+            "{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":1,\"args\":{\"name\":\"GPU\"}},\n",
+            "{\"name\":\"update\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\"ts\":10.000,\"dur\":20.000},\n",
+            "{\"name\":\"main\",\"ph\":\"X\",\"pid\":0,\"tid\":1,\"ts\":40.000,\"dur\":1.500}\n",
+            "]}\n",
+        ));
+    }
+
+    #[test]
+    fn trace_keeps_latest_events() {
+        let mut profiler = Profiler::default();
+        profiler.record_trace_event("ignored", 0, Instant::now(), Duration::ZERO);
+        assert!(profiler.trace.is_empty());
+
+        profiler.set_enabled(true);
+        let start = profiler.trace_start;
+        for index in 0..MAX_TRACE_EVENTS as u64 + 10 {
+            profiler.record_trace_event("frame", 0, start + Duration::from_micros(index), Duration::ZERO);
+        }
+        assert_eq!(profiler.trace.len(), MAX_TRACE_EVENTS);
+        assert_eq!(profiler.trace.front().unwrap().start, Duration::from_micros(10));
+    }
+}
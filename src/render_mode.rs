@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+
+use crate::blend_mode::BlendMode;
+use crate::{main_instance, tx, vertex};
+
+/**
+    What main pass draws. Everything but `Shaded` is meant for debugging the scene,
+    modes are switched with number keys.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RenderMode {
+    #[default]
+    Shaded,
+    Wireframe,
+    // Texture coordinates as red and green.
+    Uv,
+    // Every texture layer gets its own colour.
+    TextureIndex,
+    // Every sampler of the scene gets its own colour.
+    Sampler,
+    // Face normals in world space.
+    Normals,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 6] = [
+        RenderMode::Shaded,
+        RenderMode::Wireframe,
+        RenderMode::Uv,
+        RenderMode::TextureIndex,
+        RenderMode::Sampler,
+        RenderMode::Normals,
+    ];
+
+    fn fragment_entry_point(self) -> &'static str {
+        match self {
+            RenderMode::Shaded => "fs_main",
+            RenderMode::Wireframe => "fs_wireframe",
+            RenderMode::Uv => "fs_uv",
+            RenderMode::TextureIndex => "fs_texture_index",
+            RenderMode::Sampler => "fs_sampler",
+            RenderMode::Normals => "fs_normals",
+        }
+    }
+}
+
+// With this feature wireframe is rasterized as lines, without it wireframe is drawn
+// by main shader from barycentric coordinates of triangles.
+pub const WIREFRAME_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
+
+/**
+    Pipeline of main pass for every render mode and shaded pipeline of transparent pass
+    for every transparent blend mode. All of them share layout and shader.
+*/
+pub struct MainPipelines {
+    pipelines: Vec<wgpu::RenderPipeline>,
+    transparent_pipelines: Vec<wgpu::RenderPipeline>,
+    // When false wireframe pipeline expects unindexed meshes, see `MeshBuffers`.
+    pub line_wireframe: bool,
+}
+
+impl MainPipelines {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let line_wireframe = device.features().contains(WIREFRAME_FEATURES);
+        if !line_wireframe {
+            log::warn!("Adapter does not support line polygon mode, wireframe is drawn by shader");
+        }
+        let pipelines = RenderMode::ALL.iter()
+            .map(|mode| {
+                let (vertex_entry_point, fragment_entry_point, polygon_mode) = match mode {
+                    RenderMode::Wireframe if line_wireframe => ("vs_main", "fs_wireframe_lines", wgpu::PolygonMode::Line),
+                    RenderMode::Wireframe => ("vs_wireframe", mode.fragment_entry_point(), wgpu::PolygonMode::Fill),
+                    _ => ("vs_main", mode.fragment_entry_point(), wgpu::PolygonMode::Fill),
+                };
+                Self::create_pipeline(
+                    device, layout, shader, format, sample_count,
+                    vertex_entry_point, fragment_entry_point, polygon_mode, BlendMode::Opaque,
+                )
+            })
+            .collect();
+        let transparent_pipelines = BlendMode::TRANSPARENT.iter()
+            .map(|blend_mode| {
+                Self::create_pipeline(
+                    device, layout, shader, format, sample_count,
+                    "vs_main", RenderMode::Shaded.fragment_entry_point(), wgpu::PolygonMode::Fill, *blend_mode,
+                )
+            })
+            .collect();
+        Self {
+            pipelines,
+            transparent_pipelines,
+            line_wireframe,
+        }
+    }
+
+    pub fn get(&self, mode: RenderMode) -> &wgpu::RenderPipeline {
+        let index = RenderMode::ALL.iter().position(|m| *m == mode).unwrap();
+        &self.pipelines[index]
+    }
+
+    pub fn transparent(&self, blend_mode: BlendMode) -> &wgpu::RenderPipeline {
+        let index = BlendMode::TRANSPARENT.iter().position(|m| *m == blend_mode).unwrap();
+        &self.transparent_pipelines[index]
+    }
+
+    // Only wireframe drawn by shader needs every triangle to have its own vertices.
+    pub fn needs_unindexed_meshes(&self, mode: RenderMode) -> bool {
+        mode == RenderMode::Wireframe && !self.line_wireframe
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        vertex_entry_point: &str,
+        fragment_entry_point: &str,
+        polygon_mode: wgpu::PolygonMode,
+        blend_mode: BlendMode,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(fragment_entry_point),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vertex_entry_point,
+                buffers: &[
+                    vertex::Vertex::desc(),
+                    main_instance::MainInstanceRaw::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fragment_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend_mode.blend_state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE (or POINT)
+                polygon_mode,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: tx::TextureWrapper::DEPTH_FORMAT,
+                // Transparent instances are tested against depth but do not hide what is behind them.
+                depth_write_enabled: !blend_mode.is_transparent(),
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::InputEvent;
+use crate::settings::SettingsEdit;
+
+// Length of one update of the camera and everything else that depends on input.
+pub const FIXED_TIMESTEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{path}:{line}:{column}: {message}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("could not serialize recording: {0}")]
+    Serialize(String),
+    #[error("could not write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    Input(InputEvent),
+    Settings(SettingsEdit),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    // Fixed update the event was handled before, replay depends only on this.
+    pub tick: u64,
+    // Seconds since recording started, only informative.
+    pub time: f32,
+    pub event: ReplayEvent,
+}
+
+/**
+    Input events and settings panel edits in the order they were handled. Replaying them with
+    the same scene gives the same camera and toggles, because everything that depends on input is updated
+    in fixed steps and events are applied before the same step they were recorded at.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    // Updates per second the recording was made with.
+    pub tick_rate: u32,
+    // Number of updates from start to end of recording.
+    pub ticks: u64,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    pub fn tick_rate() -> u32 {
+        (Duration::from_secs(1).as_nanos() / FIXED_TIMESTEP.as_nanos()) as u32
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let source = std::fs::read_to_string(path).map_err(|source| ReplayError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let recording: Recording = ron::de::from_str(&source).map_err(|e| ReplayError::Parse {
+            path: path.to_path_buf(),
+            line: e.position.line,
+            column: e.position.col,
+            message: e.code.to_string(),
+        })?;
+        if recording.tick_rate != Self::tick_rate() {
+            log::warn!(
+                "{} was recorded with {} updates per second but sandbox runs {}, replay will differ",
+                path.display(), recording.tick_rate, Self::tick_rate(),
+            );
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| ReplayError::Serialize(e.to_string()))?;
+        std::fs::write(path, source).map_err(|source| ReplayError::Write {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    start: Instant,
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            start: Instant::now(),
+            recording: Recording {
+                tick_rate: Recording::tick_rate(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, event: ReplayEvent) {
+        self.recording.events.push(RecordedEvent {
+            tick,
+            time: self.start.elapsed().as_secs_f32(),
+            event,
+        });
+    }
+
+    pub fn save(mut self, ticks: u64) -> Result<PathBuf, ReplayError> {
+        self.recording.ticks = ticks;
+        self.recording.save(&self.path)?;
+        Ok(self.path)
+    }
+}
+
+pub struct Player {
+    recording: Recording,
+    // Index of the first event that was not replayed yet.
+    next: usize,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        Self { recording, next: 0 }
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.recording.ticks
+    }
+
+    // Events that have to be handled before given update.
+    pub fn take_due(&mut self, tick: u64) -> Vec<ReplayEvent> {
+        let due = self.recording.events[self.next..].iter()
+            .take_while(|event| event.tick <= tick)
+            .map(|event| event.event)
+            .collect::<Vec<_>>();
+        self.next += due.len();
+        due
+    }
+
+    pub fn is_finished(&self, tick: u64) -> bool {
+        self.next == self.recording.events.len() && tick >= self.recording.ticks
+    }
+}
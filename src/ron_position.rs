@@ -0,0 +1,291 @@
+/**
+    Parse error of RON source, 1 based position like positions of validation errors.
+*/
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/**
+    Parses RON source of a scene, camera path or input config. Their `parse` functions take
+    the path source was read from next to it only to put it into their errors, with this position.
+*/
+pub fn from_str<T: serde::de::DeserializeOwned>(source: &str) -> Result<T, ParseError> {
+    ron::de::from_str(source).map_err(|e| ParseError {
+        line: e.position.line,
+        column: e.position.col,
+        message: e.code.to_string(),
+    })
+}
+
+/**
+    Finds where a value of a scene field is written in RON source, so that validation errors can
+    point at the line and column of the offending field like parse errors do.
+
+    Fields are addressed by the same paths validation errors use, `instances[2].animation.frame_count`.
+    Struct fields, enum variants wrapping structs (`Grid((...))`, `Some(...)`) and list items are
+    followed. When part of the path is not written in the source, because it has a default value,
+    position of the deepest value that was found is returned instead.
+*/
+pub fn field_position(source: &str, field: &str) -> (usize, usize) {
+    let mut scanner = Scanner { source, offset: 0 };
+    scanner.skip_trivia();
+    let mut found = scanner.offset;
+    for segment in path_segments(field) {
+        let next = match segment {
+            Segment::Field(name) => scanner.find_field(name),
+            Segment::Index(index) => scanner.find_item(index),
+        };
+        match next {
+            Some(offset) => {
+                found = offset;
+                scanner.offset = offset;
+            }
+            None => break,
+        }
+    }
+    line_column(source, found)
+}
+
+enum Segment<'a> {
+    Field(&'a str),
+    Index(usize),
+}
+
+// `a.b[1][2].c` -> a, b, 1, 2, c
+fn path_segments(field: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    for part in field.split('.') {
+        let (name, indices) = part.split_once('[').map_or((part, ""), |(name, rest)| (name, rest));
+        if !name.is_empty() {
+            segments.push(Segment::Field(name));
+        }
+        for index in indices.split('[') {
+            if let Ok(index) = index.trim_end_matches(']').parse() {
+                segments.push(Segment::Index(index));
+            }
+        }
+    }
+    segments
+}
+
+// 1 based like positions of ron parse errors.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |line| line.chars().count()) + 1;
+    (line, column)
+}
+
+struct Scanner<'a> {
+    source: &'a str,
+    offset: usize,
+}
+
+impl Scanner<'_> {
+    fn rest(&self) -> &str {
+        &self.source[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.offset += c.len_utf8();
+        }
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("//") {
+                self.offset += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                self.offset += comment.find("*/").map_or(rest.len(), |end| end + 4);
+            } else if self.peek().is_some_and(char::is_whitespace) {
+                self.bump();
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn skip_identifier(&mut self) -> &str {
+        let start = self.offset;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.bump();
+        }
+        &self.source[start..self.offset]
+    }
+
+    // Skips one token, or a whole bracketed group when it starts one.
+    fn skip_token(&mut self) {
+        match self.peek() {
+            Some('(' | '[' | '{') => {
+                let mut depth = 0usize;
+                loop {
+                    self.skip_trivia();
+                    match self.peek() {
+                        None => return,
+                        Some('(' | '[' | '{') => depth += 1,
+                        Some(')' | ']' | '}') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                self.bump();
+                                return;
+                            }
+                        }
+                        Some('"') => {
+                            self.skip_string();
+                            continue;
+                        }
+                        Some(_) => {}
+                    }
+                    self.bump();
+                }
+            }
+            Some('"') => self.skip_string(),
+            _ => {
+                self.bump();
+            }
+        }
+    }
+
+    fn skip_string(&mut self) {
+        self.bump();
+        while let Some(c) = self.peek() {
+            self.bump();
+            match c {
+                '\\' => self.bump(),
+                '"' => return,
+                _ => {}
+            }
+        }
+    }
+
+    // Moves past field name, variant names and wrapping parentheses of newtype variants to the
+    // opening bracket of the innermost struct or list, returns false when the value is not one.
+    fn enter_value(&mut self, open: char) -> bool {
+        loop {
+            self.skip_trivia();
+            self.skip_identifier();
+            self.skip_trivia();
+            match self.peek() {
+                Some(':') => {
+                    self.bump();
+                    continue;
+                }
+                Some('[') => return open == '[',
+                Some('(') => {}
+                _ => return false,
+            }
+            let bracket = self.offset;
+            self.bump();
+            self.skip_trivia();
+            let inner = self.peek();
+            if inner == Some('(') || inner == Some('[') || self.looks_like_variant() {
+                continue;
+            }
+            self.offset = bracket;
+            return open == '(';
+        }
+    }
+
+    // `Some(Name(...))` or `Grid((...))`: identifier followed by an opening parenthesis, not a field.
+    fn looks_like_variant(&mut self) -> bool {
+        let start = self.offset;
+        let identifier = !self.skip_identifier().is_empty();
+        self.skip_trivia();
+        let variant = identifier && self.peek() == Some('(');
+        self.offset = start;
+        variant
+    }
+
+    // Offset of `name` in the struct starting at current offset.
+    fn find_field(&mut self, name: &str) -> Option<usize> {
+        if !self.enter_value('(') {
+            return None;
+        }
+        self.bump();
+        loop {
+            self.skip_trivia();
+            let start = self.offset;
+            let identifier = self.skip_identifier().to_owned();
+            self.skip_trivia();
+            if identifier.is_empty() || self.peek() != Some(':') {
+                return None;
+            }
+            if identifier == name {
+                return Some(start);
+            }
+            self.bump();
+            self.skip_item()?;
+        }
+    }
+
+    // Offset of item `index` in the list starting at current offset.
+    fn find_item(&mut self, index: usize) -> Option<usize> {
+        if !self.enter_value('[') {
+            return None;
+        }
+        self.bump();
+        for _ in 0..index {
+            self.skip_item()?;
+        }
+        self.skip_trivia();
+        (self.peek() != Some(']')).then_some(self.offset)
+    }
+
+    // Skips a value and the comma after it, fails at the end of the enclosing group.
+    fn skip_item(&mut self) -> Option<()> {
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                None | Some(')' | ']' | '}') => return None,
+                Some(',') => {
+                    self.bump();
+                    return Some(());
+                }
+                Some(_) => self.skip_token(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"// comment (with brackets]
+(
+    camera: (eye: (0.0, 1.0, 2.0), fovy: 45.0),
+    textures: ["a).png", "b.png"],
+    instances: [
+        Single((mesh: 0, texture_index: 0)),
+        Grid((
+            rows: 10,
+            animation: Some((frame_count: 0, frames_per_second: 2.0)),
+        )),
+    ],
+    skybox: Some((image: Equirectangular(path: "sky.hdr", face_size: 0))),
+)"#;
+
+    #[test]
+    fn finds_nested_fields_and_list_items() {
+        assert_eq!(field_position(SOURCE, "camera.fovy"), (3, 36));
+        assert_eq!(field_position(SOURCE, "textures[1]"), (4, 26));
+        assert_eq!(field_position(SOURCE, "instances[0].texture_index"), (6, 26));
+        assert_eq!(field_position(SOURCE, "instances[1].animation.frame_count"), (9, 30));
+        assert_eq!(field_position(SOURCE, "skybox.image.face_size"), (12, 59));
+    }
+
+    #[test]
+    fn falls_back_to_deepest_written_value() {
+        assert_eq!(field_position(SOURCE, "instances[1].blend_mode"), (7, 9));
+        assert_eq!(field_position(SOURCE, "instances[5].mesh"), (5, 5));
+        assert_eq!(field_position(SOURCE, "samplers"), (2, 1));
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+// Every sampler takes a sampler binding of main shader, and a texture binding on backends without binding arrays.
+pub const MAX_SAMPLERS: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AddressMode {
+    // Coordinates outside of [0, 1] read the texel at the edge.
+    #[default]
+    ClampToEdge,
+    // Texture tiles.
+    Repeat,
+    // Texture tiles, every other tile is flipped.
+    MirrorRepeat,
+}
+
+impl AddressMode {
+    pub fn to_wgpu(self) -> wgpu::AddressMode {
+        match self {
+            AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FilterMode {
+    #[default]
+    Nearest,
+    Linear,
+}
+
+impl FilterMode {
+    pub fn to_wgpu(self) -> wgpu::FilterMode {
+        match self {
+            FilterMode::Nearest => wgpu::FilterMode::Nearest,
+            FilterMode::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/**
+    Named sampler of the scene, instances pick one of them by its index.
+    Mip filter and LOD bias only matter for textures with mip levels (see compressed_texture.rs).
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplerDescription {
+    pub name: String,
+    #[serde(default)]
+    pub address_mode_u: AddressMode,
+    #[serde(default)]
+    pub address_mode_v: AddressMode,
+    #[serde(default)]
+    pub mag_filter: FilterMode,
+    #[serde(default)]
+    pub min_filter: FilterMode,
+    #[serde(default)]
+    pub mipmap_filter: FilterMode,
+    // Maximum anisotropy from 1 (none) to 16, above 1 all filters have to be linear.
+    // Adapters without anisotropic filtering ignore it.
+    #[serde(default = "default_anisotropy")]
+    pub anisotropy: u16,
+    // Added to mip level picked by GPU, positive values make textures blurrier.
+    #[serde(default)]
+    pub lod_bias: f32,
+}
+
+fn default_anisotropy() -> u16 {
+    1
+}
+
+impl SamplerDescription {
+    pub fn nearest() -> Self {
+        Self {
+            name: "nearest".into(),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            anisotropy: 1,
+            lod_bias: 0.0,
+        }
+    }
+
+    pub fn linear() -> Self {
+        Self {
+            name: "linear".into(),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Self::nearest()
+        }
+    }
+
+    pub fn is_linear(&self) -> bool {
+        [self.mag_filter, self.min_filter, self.mipmap_filter].iter().all(|filter| *filter == FilterMode::Linear)
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&self.name),
+            address_mode_u: self.address_mode_u.to_wgpu(),
+            address_mode_v: self.address_mode_v.to_wgpu(),
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter.to_wgpu(),
+            min_filter: self.min_filter.to_wgpu(),
+            mipmap_filter: self.mipmap_filter.to_wgpu(),
+            anisotropy_clamp: self.anisotropy,
+            ..Default::default()
+        })
+    }
+
+    // wgpu samplers have no LOD bias, main shader scales texture coordinate derivatives
+    // by this instead, which moves the mip level by `lod_bias`.
+    pub fn gradient_scale(&self) -> f32 {
+        self.lod_bias.exp2()
+    }
+}
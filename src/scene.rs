@@ -0,0 +1,857 @@
+use std::path::{Path, PathBuf};
+
+use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::FlipbookAnimation;
+use crate::blend_mode::BlendMode;
+use crate::camera::Camera;
+use crate::compressed_texture::{self, CompressedTextureError};
+use crate::main_instance::{MainInstance, UvTransform};
+use crate::render_mode::RenderMode;
+use crate::ron_position;
+use crate::sampler::{FilterMode, SamplerDescription, MAX_SAMPLERS};
+use crate::sprite::SpriteTransform;
+use crate::texture_format::TextureFormat;
+use crate::tone_mapping::ToneMapping;
+use crate::vertex::Vertex;
+
+// Scene files are written in RON (https://github.com/ron-rs/ron).
+// It maps almost one to one onto rust structs below so the easiest way to learn the format
+// is to look at these structs next to scenes/default.ron.
+//
+// Texture paths in scene file are relative to the directory of the scene file itself.
+
+#[derive(Debug, thiserror::Error)]
+pub enum SceneError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{path}:{line}:{column}: {message}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("{path}:{line}:{column}: invalid value of `{field}`: {message}")]
+    Invalid {
+        path: PathBuf,
+        // Position of the field in scene file, or of the closest enclosing value when field is not written there.
+        line: usize,
+        column: usize,
+        field: String,
+        message: String,
+    },
+    #[error("could not load compressed texture {path}: {source}")]
+    CompressedTexture {
+        path: PathBuf,
+        #[source]
+        source: CompressedTextureError,
+    },
+    #[error("could not serialize scene: {0}")]
+    Serialize(String),
+    #[error("could not write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+    pub clear_color: ClearColorDescription,
+    // All textures have to have same dimensions because they end up as layers of one texture.
+    // Besides images they can be KTX2 or DDS files with BC compressed textures, every layer of such
+    // file takes its own texture index, the next file starts after them.
+    pub textures: Vec<TextureDescription>,
+    // Samplers instances pick from by index, by default "nearest" (0) and "linear" (1).
+    #[serde(default = "default_samplers")]
+    pub samplers: Vec<SamplerDescription>,
+    #[serde(default = "default_meshes")]
+    pub meshes: Vec<MeshDescription>,
+    #[serde(default)]
+    pub instances: Vec<InstanceSource>,
+    // Drawn in screen space over the scene, a scene without instances is a 2D one.
+    #[serde(default)]
+    pub sprites: Vec<SpriteDescription>,
+    // Sampler of sprites that don't pick their own one.
+    #[serde(default)]
+    pub sprite_sampler: u32,
+    // Lights are not used by the main shader yet, they are only carried around with the scene.
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    #[serde(default)]
+    pub renderer: RendererSettings,
+    // Without skybox the background is the clear colour.
+    #[serde(default)]
+    pub skybox: Option<SkyboxDescription>,
+}
+
+/**
+    Texture written as its path, `"grass.png"`, or as path and format, `("height.png", R16Unorm)`.
+    Without format it is sRGB colour, or HDR for float images.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum TextureDescription {
+    Path(String),
+    WithFormat(String, TextureFormat),
+}
+
+// Untagged enums are read through self-describing values, which can't hold RON enum variants.
+impl<'de> Deserialize<'de> for TextureDescription {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextureVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for TextureVisitor {
+            type Value = TextureDescription;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("path of texture or (path, format)")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, path: &str) -> Result<Self::Value, E> {
+                Ok(TextureDescription::Path(path.to_owned()))
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let path = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                let format = seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+                if seq.next_element::<serde::de::IgnoredAny>()?.is_some() {
+                    return Err(serde::de::Error::invalid_length(3, &self));
+                }
+                Ok(TextureDescription::WithFormat(path, format))
+            }
+        }
+
+        deserializer.deserialize_any(TextureVisitor)
+    }
+}
+
+impl TextureDescription {
+    pub fn path(&self) -> &str {
+        match self {
+            TextureDescription::Path(path) | TextureDescription::WithFormat(path, _) => path,
+        }
+    }
+
+    pub fn format(&self) -> Option<TextureFormat> {
+        match self {
+            TextureDescription::Path(_) => None,
+            TextureDescription::WithFormat(_, format) => Some(*format),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    #[serde(default = "default_camera_speed")]
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClearColorDescription {
+    // Background used while cursor is inside the window.
+    pub cursor_in: [f64; 4],
+    // Background used while cursor is outside of the window.
+    pub cursor_out: [f64; 4],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RendererSettings {
+    #[serde(default)]
+    pub depth_visualisation: bool,
+    #[serde(default)]
+    pub render_mode: RenderMode,
+    #[serde(default)]
+    pub debug_draw: bool,
+    #[serde(default)]
+    pub stats_overlay: bool,
+    #[serde(default)]
+    pub tone_mapping: ToneMapping,
+    // In stops, every +1 doubles brightness of the scene before tone mapping.
+    #[serde(default)]
+    pub exposure: f32,
+    // Gamma of the display, 2.2 matches sRGB.
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            depth_visualisation: false,
+            render_mode: RenderMode::default(),
+            debug_draw: false,
+            stats_overlay: false,
+            tone_mapping: ToneMapping::default(),
+            exposure: 0.0,
+            gamma: default_gamma(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkyboxDescription {
+    pub image: SkyboxImage,
+    // How much of the environment instances reflect, from 0 (nothing) to 1 (they are mirrors).
+    #[serde(default)]
+    pub reflectivity: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SkyboxImage {
+    // Square images of +x, -x, +y, -y, +z and -z faces.
+    Faces([String; 6]),
+    // Panorama with longitude along width and latitude along height, converted to faces of `face_size` pixels.
+    Equirectangular { path: String, face_size: u32 },
+    // KTX2 or DDS cube map (or array of six square layers) with faces in the order above.
+    Cube(String),
+}
+
+impl SkyboxImage {
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            SkyboxImage::Faces(faces) => faces.iter().map(String::as_str).collect(),
+            SkyboxImage::Equirectangular { path, .. } | SkyboxImage::Cube(path) => vec![path.as_str()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MeshDescription {
+    // Quad lying in xy plane with bottom left corner in the origin.
+    Quad { width: f32, height: f32 },
+    Custom { vertices: Vec<Vertex>, indices: Vec<u16> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InstanceSource {
+    Single(InstanceDescription),
+    Grid(GridDescription),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceDescription {
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub rotation: RotationDescription,
+    #[serde(default)]
+    pub mesh: usize,
+    pub texture_index: u32,
+    // Older scenes wrote `use_linear_sampler: true` for the default "linear" sampler.
+    #[serde(default, alias = "use_linear_sampler", deserialize_with = "deserialize_sampler")]
+    pub sampler: u32,
+    #[serde(default)]
+    pub uv_transform: UvTransform,
+    #[serde(default)]
+    pub animation: Option<FlipbookAnimation>,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+}
+
+/**
+    Sprite drawn every frame, see `sprite::SpriteBatch::draw_sprite`.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpriteDescription {
+    pub texture_index: u32,
+    // x, y, width and height in texels, whole texture without it.
+    #[serde(default)]
+    pub rect: Option<[f32; 4]>,
+    #[serde(default)]
+    pub transform: SpriteTransform,
+    #[serde(default = "default_tint")]
+    pub tint: [f32; 4],
+    // Scene `sprite_sampler` without it.
+    #[serde(default)]
+    pub sampler: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RotationDescription {
+    AxisAngle { axis: [f32; 3], degrees: f32 },
+    // (x, y, z, w)
+    Quaternion([f32; 4]),
+}
+
+impl Default for RotationDescription {
+    fn default() -> Self {
+        RotationDescription::Quaternion([0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+/**
+    Generates `rows * columns` instances laid out on xz plane.
+    Each instance is rotated by `rotation_degrees` around its own (normalised) position
+    and textures / samplers are picked per row, cycling through provided lists.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridDescription {
+    pub rows: u32,
+    pub columns: u32,
+    #[serde(default = "default_spacing")]
+    pub spacing: f32,
+    #[serde(default)]
+    pub displacement: [f32; 3],
+    #[serde(default)]
+    pub rotation_degrees: f32,
+    #[serde(default)]
+    pub mesh: usize,
+    pub row_textures: Vec<u32>,
+    // Older scenes wrote `row_linear_sampler: [false, true]`, picking between the default samplers.
+    #[serde(default = "default_row_samplers", alias = "row_linear_sampler", deserialize_with = "deserialize_row_samplers")]
+    pub row_samplers: Vec<u32>,
+    // The same for all instances of the grid.
+    #[serde(default)]
+    pub uv_transform: UvTransform,
+    #[serde(default)]
+    pub animation: Option<FlipbookAnimation>,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LightDescription {
+    Point { position: [f32; 3], color: [f32; 3], intensity: f32 },
+    Directional { direction: [f32; 3], color: [f32; 3], intensity: f32 },
+}
+
+fn default_meshes() -> Vec<MeshDescription> {
+    vec![MeshDescription::Quad { width: 0.5, height: 0.5 }]
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_camera_speed() -> f32 {
+    0.2
+}
+
+fn default_gamma() -> f32 {
+    2.2
+}
+
+fn default_spacing() -> f32 {
+    1.0
+}
+
+fn default_tint() -> [f32; 4] {
+    crate::sprite::WHITE
+}
+
+fn default_samplers() -> Vec<SamplerDescription> {
+    vec![SamplerDescription::nearest(), SamplerDescription::linear()]
+}
+
+fn default_row_samplers() -> Vec<u32> {
+    vec![0]
+}
+
+// Index of a sampler, or a boolean from scenes written before samplers could be configured.
+struct SamplerIndex(u32);
+
+impl<'de> Deserialize<'de> for SamplerIndex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SamplerVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for SamplerVisitor {
+            type Value = SamplerIndex;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("index of sampler")
+            }
+
+            // False and true are the default "nearest" and "linear" samplers.
+            fn visit_bool<E: serde::de::Error>(self, linear: bool) -> Result<Self::Value, E> {
+                Ok(SamplerIndex(linear as u32))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, index: u64) -> Result<Self::Value, E> {
+                u32::try_from(index)
+                    .map(SamplerIndex)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(index), &self))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, index: i64) -> Result<Self::Value, E> {
+                u32::try_from(index)
+                    .map(SamplerIndex)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(index), &self))
+            }
+        }
+
+        deserializer.deserialize_any(SamplerVisitor)
+    }
+}
+
+fn deserialize_sampler<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    SamplerIndex::deserialize(deserializer).map(|index| index.0)
+}
+
+fn deserialize_row_samplers<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u32>, D::Error> {
+    Vec::<SamplerIndex>::deserialize(deserializer).map(|indices| indices.into_iter().map(|index| index.0).collect())
+}
+
+/**
+    Scene loaded from disk together with bytes of all textures it references.
+*/
+pub struct Scene {
+    pub path: PathBuf,
+    pub description: SceneDescription,
+    pub texture_bytes: Vec<Vec<u8>>,
+    // Number of layers of scene texture array, compressed textures can have more than one.
+    pub texture_layers: u32,
+    // Bytes of skybox images in the order of `SkyboxImage::paths`.
+    pub skybox_bytes: Vec<Vec<u8>>,
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        let source = std::fs::read_to_string(path).map_err(|source| SceneError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let description = SceneDescription::parse(&source, path)?;
+
+        let read_relative = |relative: &str| {
+            let texture_path = resolve_relative(path, relative);
+            std::fs::read(&texture_path).map_err(|source| SceneError::Io {
+                path: texture_path,
+                source,
+            })
+        };
+        let texture_bytes = description.textures.iter()
+            .map(|texture| read_relative(texture.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let texture_layers = texture_bytes.iter()
+            .zip(&description.textures)
+            .map(|(bytes, texture)| compressed_texture::layer_count(bytes).map_err(|source| SceneError::CompressedTexture {
+                path: resolve_relative(path, texture.path()),
+                source,
+            }))
+            .sum::<Result<u32, _>>()?;
+        // Indices of textures can be checked only when it is known how many layers they have.
+        description.validate(&source, path, texture_layers)?;
+        let skybox_bytes = description.skybox.iter()
+            .flat_map(|skybox| skybox.image.paths())
+            .map(read_relative)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            description,
+            texture_bytes,
+            texture_layers,
+            skybox_bytes,
+        })
+    }
+}
+
+/**
+    Path where scene loaded from `scene_path` is saved.
+    It stays in the same directory so that relative texture paths are still valid.
+*/
+pub fn save_path(scene_path: &Path) -> PathBuf {
+    let stem = scene_path.file_stem().and_then(|s| s.to_str()).unwrap_or("scene");
+    if stem.ends_with(".saved") {
+        scene_path.to_path_buf()
+    } else {
+        scene_path.with_file_name(format!("{}.saved.ron", stem))
+    }
+}
+
+fn resolve_relative(scene_path: &Path, relative: &str) -> PathBuf {
+    scene_path.parent().unwrap_or_else(|| Path::new(".")).join(relative)
+}
+
+impl SceneDescription {
+    pub fn parse(source: &str, path: &Path) -> Result<Self, SceneError> {
+        ron_position::from_str(source).map_err(|e| SceneError::Parse {
+            path: path.to_path_buf(),
+            line: e.line,
+            column: e.column,
+            message: e.message,
+        })
+    }
+
+    pub fn to_ron_string(&self) -> Result<String, SceneError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| SceneError::Serialize(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SceneError> {
+        std::fs::write(path, self.to_ron_string()?).map_err(|source| SceneError::Write {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /**
+        Returns copy of this scene with camera and instances replaced by their current values.
+        Generated instances are saved one by one so grids are expanded in the result.
+    */
+    pub fn with_runtime_state(&self, camera: &Camera, camera_speed: f32, instances: &[MainInstance]) -> Self {
+        Self {
+            camera: CameraDescription {
+                eye: camera.eye.into(),
+                target: camera.target.into(),
+                up: camera.up.into(),
+                fovy: camera.fovy,
+                znear: camera.znear,
+                zfar: camera.zfar,
+                speed: camera_speed,
+            },
+            instances: instances.iter().map(|instance| InstanceSource::Single(InstanceDescription {
+                position: instance.position.into(),
+                rotation: RotationDescription::Quaternion([
+                    instance.rotation.v.x,
+                    instance.rotation.v.y,
+                    instance.rotation.v.z,
+                    instance.rotation.s,
+                ]),
+                mesh: instance.mesh_index,
+                texture_index: instance.texture_index as u32,
+                sampler: instance.sampler_index as u32,
+                uv_transform: instance.uv_transform,
+                animation: instance.animation,
+                blend_mode: instance.blend_mode,
+            })).collect(),
+            ..self.clone()
+        }
+    }
+
+    // `source` is the RON this scene was parsed from, errors point at line and column of invalid field in it.
+    // `texture_layers` is the number of layers of all textures, see `Scene::texture_layers`.
+    pub fn validate(&self, source: &str, path: &Path, texture_layers: u32) -> Result<(), SceneError> {
+        let invalid = |field: String, message: String| {
+            let (line, column) = ron_position::field_position(source, &field);
+            SceneError::Invalid {
+                path: path.to_path_buf(),
+                line,
+                column,
+                field,
+                message,
+            }
+        };
+
+        let camera = &self.camera;
+        if !(camera.fovy > 0.0 && camera.fovy < 180.0) {
+            return Err(invalid("camera.fovy".into(), format!("{} is not in range (0, 180)", camera.fovy)));
+        }
+        if !(camera.znear > 0.0 && camera.znear < camera.zfar) {
+            return Err(invalid(
+                "camera.znear".into(),
+                format!("expected 0 < znear < zfar, got znear {} and zfar {}", camera.znear, camera.zfar),
+            ));
+        }
+        if camera.eye == camera.target {
+            return Err(invalid("camera.target".into(), "camera target can't be equal to eye".into()));
+        }
+
+        if self.textures.is_empty() {
+            return Err(invalid("textures".into(), "scene needs at least one texture".into()));
+        }
+        if self.meshes.is_empty() {
+            return Err(invalid("meshes".into(), "scene needs at least one mesh".into()));
+        }
+
+        for (mesh_idx, mesh) in self.meshes.iter().enumerate() {
+            if let MeshDescription::Custom { vertices, indices } = mesh {
+                if indices.len() % 3 != 0 {
+                    return Err(invalid(
+                        format!("meshes[{}].indices", mesh_idx),
+                        format!("number of indices ({}) is not multiple of 3", indices.len()),
+                    ));
+                }
+                if let Some(index) = indices.iter().find(|i| **i as usize >= vertices.len()) {
+                    return Err(invalid(
+                        format!("meshes[{}].indices", mesh_idx),
+                        format!("index {} is out of range, mesh has {} vertices", index, vertices.len()),
+                    ));
+                }
+            }
+        }
+
+        if self.samplers.is_empty() || self.samplers.len() > MAX_SAMPLERS {
+            return Err(invalid(
+                "samplers".into(),
+                format!("scene needs from 1 to {} samplers, got {}", MAX_SAMPLERS, self.samplers.len()),
+            ));
+        }
+        for (sampler_idx, sampler) in self.samplers.iter().enumerate() {
+            if !(1..=16).contains(&sampler.anisotropy) {
+                return Err(invalid(
+                    format!("samplers[{}].anisotropy", sampler_idx),
+                    format!("{} is not in range [1, 16]", sampler.anisotropy),
+                ));
+            }
+            if sampler.anisotropy > 1 && !sampler.is_linear() {
+                return Err(invalid(
+                    format!("samplers[{}].anisotropy", sampler_idx),
+                    format!("anisotropic filtering requires {:?} mag, min and mipmap filters", FilterMode::Linear),
+                ));
+            }
+            if !(-16.0..=16.0).contains(&sampler.lod_bias) {
+                return Err(invalid(
+                    format!("samplers[{}].lod_bias", sampler_idx),
+                    format!("{} is not in range [-16, 16]", sampler.lod_bias),
+                ));
+            }
+        }
+
+        let check_mesh = |field: String, mesh: usize| {
+            if mesh >= self.meshes.len() {
+                return Err(invalid(field, format!("mesh {} does not exist, scene has {} meshes", mesh, self.meshes.len())));
+            }
+            Ok(())
+        };
+        let check_texture = |field: String, texture: u32| {
+            if texture >= texture_layers {
+                return Err(invalid(field, format!("texture {} does not exist, scene has {} texture layers", texture, texture_layers)));
+            }
+            Ok(())
+        };
+        let check_sampler = |field: String, sampler: u32| {
+            if sampler as usize >= self.samplers.len() {
+                return Err(invalid(field, format!("sampler {} does not exist, scene has {} samplers", sampler, self.samplers.len())));
+            }
+            Ok(())
+        };
+        let check_blend_mode = |field: String, blend_mode: BlendMode| {
+            if let BlendMode::AlphaTest { cutoff } = blend_mode {
+                if !(0.0..=1.0).contains(&cutoff) {
+                    return Err(invalid(format!("{}.cutoff", field), format!("{} is not in range [0, 1]", cutoff)));
+                }
+            }
+            Ok(())
+        };
+        // Frames of layer animations are texture layers following the first one.
+        let check_animation = |field: String, animation: &Option<FlipbookAnimation>, first_textures: &[u32]| {
+            let Some(animation) = animation else {
+                return Ok(());
+            };
+            if animation.frame_count == 0 {
+                return Err(invalid(format!("{}.frame_count", field), "animation needs at least one frame".into()));
+            }
+            // The shader gets frame count and sheet columns as signed integers.
+            if animation.frame_count > i32::MAX as u32 {
+                return Err(invalid(format!("{}.frame_count", field), format!("{} is more than {}", animation.frame_count, i32::MAX)));
+            }
+            if !(animation.frames_per_second.is_finite() && animation.frames_per_second >= 0.0) {
+                return Err(invalid(
+                    format!("{}.frames_per_second", field),
+                    format!("{} is not a finite non negative number", animation.frames_per_second),
+                ));
+            }
+            match animation.sheet_columns {
+                Some(0) => return Err(invalid(format!("{}.sheet_columns", field), "sheet needs at least one column".into())),
+                Some(columns) if columns > i32::MAX as u32 => {
+                    return Err(invalid(format!("{}.sheet_columns", field), format!("{} is more than {}", columns, i32::MAX)));
+                }
+                Some(_) => {}
+                None => {
+                    for texture in first_textures {
+                        let last = animation.frame_count.checked_sub(1).and_then(|frames| texture.checked_add(frames));
+                        match last {
+                            Some(last) if last < texture_layers => {}
+                            Some(last) => {
+                                return Err(invalid(
+                                    format!("{}.frame_count", field),
+                                    format!("last frame is texture {}, scene has {} texture layers", last, texture_layers),
+                                ));
+                            }
+                            None => {
+                                return Err(invalid(
+                                    format!("{}.frame_count", field),
+                                    format!("{} frames starting at texture {} are more than texture indices can hold", animation.frame_count, texture),
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        for (source_idx, source) in self.instances.iter().enumerate() {
+            match source {
+                InstanceSource::Single(instance) => {
+                    check_mesh(format!("instances[{}].mesh", source_idx), instance.mesh)?;
+                    check_texture(format!("instances[{}].texture_index", source_idx), instance.texture_index)?;
+                    check_sampler(format!("instances[{}].sampler", source_idx), instance.sampler)?;
+                    check_animation(format!("instances[{}].animation", source_idx), &instance.animation, &[instance.texture_index])?;
+                    check_blend_mode(format!("instances[{}].blend_mode", source_idx), instance.blend_mode)?;
+                    match instance.rotation {
+                        RotationDescription::AxisAngle { axis, .. } if cgmath::Vector3::from(axis).is_zero() => {
+                            return Err(invalid(format!("instances[{}].rotation.axis", source_idx), "axis can't be zero vector".into()));
+                        }
+                        // Instance rotation is normalised, which a zero quaternion can't be.
+                        RotationDescription::Quaternion(quaternion) if cgmath::Vector4::from(quaternion).is_zero() => {
+                            return Err(invalid(format!("instances[{}].rotation", source_idx), "quaternion can't be zero".into()));
+                        }
+                        _ => {}
+                    }
+                }
+                InstanceSource::Grid(grid) => {
+                    check_mesh(format!("instances[{}].mesh", source_idx), grid.mesh)?;
+                    if grid.row_textures.is_empty() {
+                        return Err(invalid(format!("instances[{}].row_textures", source_idx), "at least one texture is required".into()));
+                    }
+                    if grid.row_samplers.is_empty() {
+                        return Err(invalid(format!("instances[{}].row_samplers", source_idx), "at least one sampler is required".into()));
+                    }
+                    for (row_idx, texture) in grid.row_textures.iter().enumerate() {
+                        check_texture(format!("instances[{}].row_textures[{}]", source_idx, row_idx), *texture)?;
+                    }
+                    for (row_idx, sampler) in grid.row_samplers.iter().enumerate() {
+                        check_sampler(format!("instances[{}].row_samplers[{}]", source_idx, row_idx), *sampler)?;
+                    }
+                    check_animation(format!("instances[{}].animation", source_idx), &grid.animation, &grid.row_textures)?;
+                    check_blend_mode(format!("instances[{}].blend_mode", source_idx), grid.blend_mode)?;
+                }
+            }
+        }
+
+        check_sampler("sprite_sampler".into(), self.sprite_sampler)?;
+        for (sprite_idx, sprite) in self.sprites.iter().enumerate() {
+            check_texture(format!("sprites[{}].texture_index", sprite_idx), sprite.texture_index)?;
+            if let Some(sampler) = sprite.sampler {
+                check_sampler(format!("sprites[{}].sampler", sprite_idx), sampler)?;
+            }
+            if let Some([_, _, width, height]) = sprite.rect {
+                if width <= 0.0 || height <= 0.0 {
+                    return Err(invalid(format!("sprites[{}].rect", sprite_idx), format!("size {}x{} is not positive", width, height)));
+                }
+            }
+        }
+
+        if self.renderer.gamma <= 0.0 {
+            return Err(invalid("renderer.gamma".into(), format!("{} is not positive", self.renderer.gamma)));
+        }
+
+        if let Some(skybox) = &self.skybox {
+            if !(0.0..=1.0).contains(&skybox.reflectivity) {
+                return Err(invalid("skybox.reflectivity".into(), format!("{} is not in range [0, 1]", skybox.reflectivity)));
+            }
+            if let SkyboxImage::Equirectangular { face_size: 0, .. } = skybox.image {
+                return Err(invalid("skybox.image.face_size".into(), "face size can't be zero".into()));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn build_camera(&self, aspect: f32) -> Camera {
+        Camera {
+            eye: self.camera.eye.into(),
+            target: self.camera.target.into(),
+            up: self.camera.up.into(),
+            aspect,
+            fovy: self.camera.fovy,
+            znear: self.camera.znear,
+            zfar: self.camera.zfar,
+        }
+    }
+
+    pub fn clear_colors(&self) -> (wgpu::Color, wgpu::Color) {
+        let to_color = |[r, g, b, a]: [f64; 4]| wgpu::Color { r, g, b, a };
+        (to_color(self.clear_color.cursor_in), to_color(self.clear_color.cursor_out))
+    }
+
+    pub fn set_clear_colors(&mut self, cursor_in: wgpu::Color, cursor_out: wgpu::Color) {
+        let from_color = |c: wgpu::Color| [c.r, c.g, c.b, c.a];
+        self.clear_color = ClearColorDescription {
+            cursor_in: from_color(cursor_in),
+            cursor_out: from_color(cursor_out),
+        };
+    }
+
+    pub fn build_meshes(&self) -> Vec<(Vec<Vertex>, Vec<u16>)> {
+        self.meshes.iter().map(|mesh| match mesh {
+            MeshDescription::Quad { width, height } => (
+                vec![
+                    Vertex { position: [0.0, 0.0, 0.0], tex_coords: [0.0, 0.0] },
+                    Vertex { position: [*width, 0.0, 0.0], tex_coords: [1.0, 0.0] },
+                    Vertex { position: [*width, *height, 0.0], tex_coords: [1.0, 1.0] },
+                    Vertex { position: [0.0, *height, 0.0], tex_coords: [0.0, 1.0] },
+                ],
+                vec![0, 1, 2, 2, 3, 0],
+            ),
+            MeshDescription::Custom { vertices, indices } => (vertices.clone(), indices.clone()),
+        }).collect()
+    }
+
+    pub fn build_instances(&self) -> Vec<MainInstance> {
+        self.instances.iter().flat_map(|source| match source {
+            InstanceSource::Single(instance) => vec![MainInstance {
+                position: instance.position.into(),
+                rotation: instance.rotation.to_quaternion(),
+                sampler_index: instance.sampler as i32,
+                texture_index: instance.texture_index as i32,
+                uv_transform: instance.uv_transform,
+                animation: instance.animation,
+                blend_mode: instance.blend_mode,
+                mesh_index: instance.mesh,
+            }],
+            InstanceSource::Grid(grid) => grid.build_instances(),
+        }).collect()
+    }
+}
+
+impl RotationDescription {
+    pub fn to_quaternion(&self) -> cgmath::Quaternion<f32> {
+        match self {
+            RotationDescription::AxisAngle { axis, degrees } => {
+                cgmath::Quaternion::from_axis_angle(cgmath::Vector3::from(*axis).normalize(), cgmath::Deg(*degrees))
+            }
+            RotationDescription::Quaternion([x, y, z, w]) => cgmath::Quaternion::new(*w, *x, *y, *z),
+        }
+    }
+}
+
+impl GridDescription {
+    fn build_instances(&self) -> Vec<MainInstance> {
+        let displacement = cgmath::Vector3::from(self.displacement);
+        (0..self.rows).flat_map(|z| {
+            (0..self.columns).map(move |x| {
+                let position = cgmath::Vector3 {
+                    x: x as f32 * self.spacing,
+                    y: 0.0,
+                    z: (self.rows - z) as f32 * self.spacing,
+                } - displacement;
+                let rotation = if position.is_zero() {
+                    // this is needed so an object at (0, 0, 0) won't get scaled to zero
+                    // as Quaternions can effect scale if they're not created correctly
+                    cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+                } else {
+                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(self.rotation_degrees))
+                };
+                let row = z as usize;
+                MainInstance {
+                    position,
+                    rotation,
+                    sampler_index: self.row_samplers[row % self.row_samplers.len()] as i32,
+                    texture_index: self.row_textures[row % self.row_textures.len()] as i32,
+                    uv_transform: self.uv_transform,
+                    animation: self.animation,
+                    blend_mode: self.blend_mode,
+                    mesh_index: self.mesh,
+                }
+            })
+        }).collect()
+    }
+}
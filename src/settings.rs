@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+use crate::input::Action;
+use crate::tone_mapping::ToneMapping;
+
+/**
+    Change made in the settings panel. The panel does not change the sandbox itself, its changes
+    are applied and recorded the same way as input events, so replays include them.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SettingsEdit {
+    // Same as pressing a key bound to the action.
+    Action(Action),
+    Fovy(f32),
+    Znear(f32),
+    Zfar(f32),
+    CameraSpeed(f32),
+    ClearCameraPath,
+    CameraPathTime(f32),
+    ToneMapping(ToneMapping),
+    Exposure(f32),
+    Gamma(f32),
+    Reflectivity(f32),
+    ClearColor { cursor_in: bool, rgb: [f32; 3] },
+    InstancePosition { index: usize, position: [f32; 3] },
+    InstanceTexture { index: usize, texture_index: i32 },
+    InstanceSampler { index: usize, sampler_index: i32 },
+}
@@ -0,0 +1,32 @@
+// Same as depth_visualisation_shader.wgsl but for multisampled depth buffer (when MSAA is on).
+// Multisampled textures can't be sampled, only single samples can be loaded from them.
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var depth_buffer_texture: texture_multisampled_2d<f32>;
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(depth_buffer_texture));
+    let coords = min(vec2<i32>(in.tex_coords * vec2<f32>(size)), size - vec2<i32>(1, 1));
+    let depth = textureLoad(depth_buffer_texture, coords, 0).r;
+    return vec4<f32>(depth, 0.0, 0.0, 1.0);
+}
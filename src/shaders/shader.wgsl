@@ -1,16 +1,40 @@
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    time: f32,
 };
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+// Environment of the skybox, see skybox.rs and skybox.wgsl.
+struct EnvironmentUniform {
+    inverse_rotation_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    reflectivity: f32,
+};
+@group(2) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(2) @binding(1)
+var environment_sampler: sampler;
+@group(2) @binding(2)
+var<uniform> environment: EnvironmentUniform;
+
 struct InstanceInput {
     @location(5) model_matrix_0: vec4<f32>,
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
-    @location(9) use_linear_sampler: i32,
-    @location(10) texture_index: i32
+    @location(9) sampler_index: i32,
+    @location(10) texture_index: i32,
+    // Rows of 2x3 matrix transforming texture coordinates, followed by scroll speed of u and v,
+    // see main_instance.rs.
+    @location(11) uv_transform_0: vec4<f32>,
+    @location(12) uv_transform_1: vec4<f32>,
+    // Frame count, mode and sheet columns of flipbook animation, see animation.rs.
+    @location(13) animation_frames: vec3<i32>,
+    // Frames per second and start time.
+    @location(14) animation_timing: vec2<f32>,
+    // Pixels with lower alpha are discarded, see blend_mode.rs.
+    @location(15) alpha_cutoff: f32,
 };
 
 struct VertexInput {
@@ -21,14 +45,25 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
-    @location(1) use_linear_sampler: i32,
-    @location(2) texture_index: i32
+    @location(1) sampler_index: i32,
+    @location(2) texture_index: i32,
+    @location(3) world_position: vec3<f32>,
+    @location(4) alpha_cutoff: f32,
 };
 
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput
+) -> VertexOutput {
+    return transform_vertex(model, instance);
+}
+
+// Entry points can't be called from other functions so vertex transformation shared by
+// vertex shaders lives here.
+fn transform_vertex(
+    model: VertexInput,
+    instance: InstanceInput
 ) -> VertexOutput {
    let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
@@ -38,39 +73,151 @@ fn vs_main(
     );
 
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
-    out.use_linear_sampler = instance.use_linear_sampler;
-    out.texture_index = instance.texture_index;
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    let frame = animation_frame(instance.animation_frames.x, instance.animation_frames.y, instance.animation_timing);
+    let sheet_columns = instance.animation_frames.z;
+    var cell = vec2<f32>(0.0);
+    var texture_index = instance.texture_index;
+    if(sheet_columns > 0) {
+        // Cells are moved in coordinates before UV transform, where one cell is one unit.
+        cell = vec2<f32>(f32(frame % sheet_columns), f32(frame / sheet_columns));
+    } else {
+        texture_index += frame;
+    }
+    let uv = vec3<f32>(model.tex_coords + cell, 1.0);
+    // Only the fraction of scrolled distance is added, so it does not lose precision over time.
+    let scroll = fract(vec2<f32>(instance.uv_transform_0.w, instance.uv_transform_1.w) * camera.time);
+    out.tex_coords = vec2<f32>(dot(instance.uv_transform_0.xyz, uv), dot(instance.uv_transform_1.xyz, uv)) + scroll;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    out.sampler_index = instance.sampler_index;
+    out.texture_index = texture_index;
+    out.alpha_cutoff = instance.alpha_cutoff;
     return out;
 }
 
+// Index of flipbook frame shown at current time, modes match `AnimationMode`.
+fn animation_frame(frame_count: i32, mode: i32, timing: vec2<f32>) -> i32 {
+    if(frame_count <= 1) {
+        return 0;
+    }
+    // Floats so that frame number does not overflow in long runs.
+    let step = floor(max(camera.time - timing.y, 0.0) * timing.x);
+    let count = f32(frame_count);
+    switch mode {
+        case 1: {
+            let period = 2.0 * count - 2.0;
+            let position = step % period;
+            return i32(select(position, period - position, position >= count));
+        }
+        case 2: {
+            return i32(min(step, count - 1.0));
+        }
+        default: {
+            return i32(step % count);
+        }
+    }
+}
 
-@group(0) @binding(0)
-var my_textures: texture_2d_array<f32>;
-@group(0) @binding(1)
-var linear_sampler: sampler;
-@group(0) @binding(2)
-var nearest_sampler: sampler;
+// Texture bindings together with sample_texture function are generated by main_bind_group.rs
+// to match samplers of the scene.
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-//     See this to know why I use textureSampleLevel instead of textureSample.
-//     https://stackoverflow.com/questions/77100370/how-to-conditionally-sample-a-texture-in-wgsl
-//
-//     Apparently you can't use textureSample in conditional logic because of some Uniformity requirements.
-//     See this https://www.w3.org/TR/WGSL/#uniformity
-//
-//     As I vaugly understand it has something to do with the fact that this code can't be run
-//     trully concurrently?
-    if(in.use_linear_sampler == 1) {
-    // as I understand I have one minimap per texture for now (texture itself) so I set 0 here
-    // but maybe there are no minimaps and it defaults to sampling from texture?
-    // who knows, for now I do not know almost anything about minimaps
-       return textureSampleLevel(my_textures, linear_sampler, in.tex_coords, in.texture_index, 0.0);
-    } else {
-       return textureSampleLevel(my_textures, nearest_sampler, in.tex_coords, in.texture_index, 0.0);
+    let colour = sample_instance_texture(in);
+    // Derivatives need uniform control flow, so pixel is discarded only after they are used.
+    let shaded = reflect_environment(colour, in.world_position);
+    if(colour.a < in.alpha_cutoff) {
+        discard;
     }
+    return shaded;
+}
+
+// Mixes colour with environment reflected by the face, mirror-like with reflectivity 1.
+fn reflect_environment(colour: vec4<f32>, world_position: vec3<f32>) -> vec4<f32> {
+    let normal = normalize(cross(dpdx(world_position), dpdy(world_position)));
+    let view_direction = normalize(world_position - environment.camera_position.xyz);
+    let reflected = textureSample(environment_map, environment_sampler, reflect(view_direction, normal));
+    return vec4<f32>(mix(colour.rgb, reflected.rgb, environment.reflectivity), colour.a);
+}
+
+// Debug render modes, see render_mode.rs.
+
+// Apparently you can't use textureSample in conditional logic because of some Uniformity requirements.
+// See this https://www.w3.org/TR/WGSL/#uniformity
+//
+// Sampler is picked by a switch so derivatives are computed before it, in uniform control flow,
+// and textureSampleGrad gets them explicitly. This way mip levels still work.
+fn sample_instance_texture(in: VertexOutput) -> vec4<f32> {
+    let ddx = dpdx(in.tex_coords);
+    let ddy = dpdy(in.tex_coords);
+    return sample_texture(in.tex_coords, in.texture_index, in.sampler_index, ddx, ddy);
 }
 
+@fragment
+fn fs_uv(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(fract(in.tex_coords), 0.0, 1.0);
+}
 
+@fragment
+fn fs_texture_index(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Golden angle spreads hues of consecutive indices far from each other.
+    let hue = fract(f32(in.texture_index) * 0.381966);
+    let colour = clamp(abs(fract(vec3<f32>(hue) + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+    // A bit of texture is left so that it is still visible what is drawn.
+    let luminance = dot(sample_instance_texture(in).rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return vec4<f32>(colour * (0.6 + 0.4 * luminance), 1.0);
+}
+
+@fragment
+fn fs_sampler(in: VertexOutput) -> @location(0) vec4<f32> {
+    let hue = fract(f32(in.sampler_index) * 0.381966);
+    let colour = clamp(abs(fract(vec3<f32>(hue) + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+    let luminance = dot(sample_instance_texture(in).rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return vec4<f32>(colour * (0.6 + 0.4 * luminance), 1.0);
+}
+
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Vertices have no normals so face normal is computed from how position changes across the screen.
+    let normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    return vec4<f32>(normal * 0.5 + 0.5, 1.0);
+}
+
+// Used when wireframe is rasterized as lines.
+@fragment
+fn fs_wireframe_lines(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
+
+struct WireframeVertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) barycentric: vec3<f32>,
+};
+
+// Used with unindexed meshes where every three consecutive vertices make a triangle.
+@vertex
+fn vs_wireframe(
+    @builtin(vertex_index) vertex_index: u32,
+    model: VertexInput,
+    instance: InstanceInput
+) -> WireframeVertexOutput {
+    let main_out = transform_vertex(model, instance);
+    var out: WireframeVertexOutput;
+    out.clip_position = main_out.clip_position;
+    let corner = vertex_index % 3u;
+    out.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+    return out;
+}
+
+@fragment
+fn fs_wireframe(in: WireframeVertexOutput) -> @location(0) vec4<f32> {
+    // Pixel is on the edge when it is close to zero in any of barycentric coordinates,
+    // fwidth keeps lines about one pixel wide no matter how far triangle is.
+    let distance = in.barycentric / fwidth(in.barycentric);
+    let edge = min(min(distance.x, distance.y), distance.z);
+    if(edge > 1.0) {
+        discard;
+    }
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
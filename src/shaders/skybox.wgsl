@@ -0,0 +1,36 @@
+struct EnvironmentUniform {
+    // Inverse of rotation projection matrix of the camera, it turns clip space into directions.
+    inverse_rotation_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    reflectivity: f32,
+};
+@group(0) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(0) @binding(1)
+var environment_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> environment: EnvironmentUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Not divided by w yet, it is linear in screen space so it can be interpolated as it is.
+    @location(0) direction: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // One triangle covering the whole screen, there is no vertex buffer.
+    let position = vec2<f32>(f32(vertex_index / 2u) * 4.0 - 1.0, f32(vertex_index % 2u) * 4.0 - 1.0);
+    var out: VertexOutput;
+    // Depth 1 puts the sky on the far plane, behind everything drawn before it.
+    out.clip_position = vec4<f32>(position, 1.0, 1.0);
+    // Any point of the ray in front of the camera gives its direction, depth 0 is always in front.
+    // Point at depth 1 can be at infinity where w is 0.
+    out.direction = environment.inverse_rotation_projection * vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(environment_map, environment_sampler, in.direction.xyz / in.direction.w);
+}
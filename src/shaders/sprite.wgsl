@@ -0,0 +1,60 @@
+// Texture bindings together with sample_texture function are generated by main_bind_group.rs,
+// sprites use the same textures and samplers as main shader.
+
+struct SpriteUniform {
+    screen_size: vec2<f32>,
+};
+@group(1) @binding(0)
+var<uniform> sprites: SpriteUniform;
+
+// One sprite, see sprite.rs.
+struct SpriteInput {
+    @location(0) transform_0: vec3<f32>,
+    @location(1) transform_1: vec3<f32>,
+    @location(2) uv_transform_0: vec3<f32>,
+    @location(3) uv_transform_1: vec3<f32>,
+    @location(4) tint: vec4<f32>,
+    @location(5) texture_index: i32,
+    @location(6) sampler_index: i32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+    @location(2) texture_index: i32,
+    @location(3) sampler_index: i32,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    sprite: SpriteInput,
+) -> VertexOutput {
+    // Two triangles of sprite quad, there is no vertex buffer.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+    );
+    let corner = vec3<f32>(corners[vertex_index], 1.0);
+    let pixel = vec2<f32>(dot(sprite.transform_0, corner), dot(sprite.transform_1, corner));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(pixel.x / sprites.screen_size.x * 2.0 - 1.0, 1.0 - pixel.y / sprites.screen_size.y * 2.0, 0.0, 1.0);
+    out.tex_coords = vec2<f32>(dot(sprite.uv_transform_0, corner), dot(sprite.uv_transform_1, corner));
+    out.tint = sprite.tint;
+    out.texture_index = sprite.texture_index;
+    out.sampler_index = sprite.sampler_index;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ddx = dpdx(in.tex_coords);
+    let ddy = dpdy(in.tex_coords);
+    return sample_texture(in.tex_coords, in.texture_index, in.sampler_index, ddx, ddy) * in.tint;
+}
@@ -0,0 +1,54 @@
+struct ScreenUniform {
+    size: vec2<f32>,
+};
+@group(0) @binding(0)
+var font_atlas: texture_2d<f32>;
+@group(0) @binding(1)
+var font_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> screen: ScreenUniform;
+
+// One glyph, position and size are in pixels with origin in top left corner of the screen.
+struct GlyphInput {
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) uv_min: vec2<f32>,
+    @location(3) uv_max: vec2<f32>,
+    @location(4) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    glyph: GlyphInput,
+) -> VertexOutput {
+    // Two triangles of glyph quad, there is no vertex buffer.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+    );
+    let corner = corners[vertex_index];
+    let pixel = glyph.position + corner * glyph.size;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(pixel.x / screen.size.x * 2.0 - 1.0, 1.0 - pixel.y / screen.size.y * 2.0, 0.0, 1.0);
+    out.tex_coords = mix(glyph.uv_min, glyph.uv_max, corner);
+    out.color = glyph.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(font_atlas, font_sampler, in.tex_coords).a;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,86 @@
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+struct ToneMappingUniform {
+    exposure_scale: f32,
+    output_exponent: f32,
+    // See ToneMapping::shader_index.
+    curve: u32,
+};
+
+@group(0) @binding(0)
+var hdr_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var hdr_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> tone_mapping: ToneMappingUniform;
+
+const CURVE_REINHARD: u32 = 1u;
+const CURVE_ACES: u32 = 2u;
+const CURVE_AGX: u32 = 3u;
+
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    // https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Polynomial fit of AgX base contrast curve, see https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn agx(colour: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    // Log encoding of the colour in AgX space, 0 and 1 are the darkest and brightest stops kept.
+    let encoded = (clamp(log2(max(inset * colour, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev)) - min_ev) / (max_ev - min_ev);
+    // Curve gives display encoded colour, it is made linear again for the rest of the pass.
+    let display = outset * agx_contrast(encoded);
+    return pow(max(display, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr = textureSample(hdr_texture, hdr_sampler, in.tex_coords);
+    let colour = max(hdr.rgb * tone_mapping.exposure_scale, vec3<f32>(0.0));
+    var mapped: vec3<f32>;
+    if(tone_mapping.curve == CURVE_REINHARD) {
+        mapped = colour / (1.0 + colour);
+    } else if(tone_mapping.curve == CURVE_ACES) {
+        mapped = aces(colour);
+    } else if(tone_mapping.curve == CURVE_AGX) {
+        mapped = agx(colour);
+    } else {
+        mapped = min(colour, vec3<f32>(1.0));
+    }
+    return vec4<f32>(pow(mapped, vec3<f32>(tone_mapping.output_exponent)), 1.0);
+}
@@ -0,0 +1,212 @@
+use cgmath::SquareMatrix;
+
+use crate::camera::Camera;
+use crate::tx::{self, TextureWrapper};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    inverse_rotation_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    reflectivity: f32,
+    _padding: [f32; 3],
+}
+
+/**
+    Environment cube map drawn as background and reflected by instances in main shader.
+
+    Its bind group (cube map, sampler and uniform with camera) is group 0 of skybox pipeline
+    and group 2 of main pipelines. Scene without skybox gets black environment which is
+    not drawn and not reflected.
+*/
+pub struct Skybox {
+    // Kept alive for the bind group.
+    _environment: TextureWrapper,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    visible: bool,
+    pub reflectivity: f32,
+}
+
+impl Skybox {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        environment: Option<TextureWrapper>,
+        reflectivity: f32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Result<Self, crate::error::RendererError> {
+        let visible = environment.is_some();
+        let environment = match environment {
+            Some(environment) => environment,
+            None => {
+                let black = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 0, 255])));
+                TextureWrapper::cube_from_images(device, queue, &vec![black; 6], Some("empty_environment"))?
+            }
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Environment Buffer"),
+            size: std::mem::size_of::<EnvironmentUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("environment_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&environment.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("environment_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/skybox.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox Pipeline Layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Skybox Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // Sky is only drawn where depth buffer is still cleared, so it is drawn after the scene.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: tx::TextureWrapper::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Ok(Self {
+            _environment: environment,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            visible,
+            reflectivity: if visible { reflectivity } else { 0.0 },
+        })
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let inverse = camera.build_rotation_projection_matrix().invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        let uniform = EnvironmentUniform {
+            inverse_rotation_projection: inverse.into(),
+            camera_position: camera.eye.to_homogeneous().into(),
+            reflectivity: self.reflectivity,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Returns whether anything was drawn.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) -> bool {
+        if !self.visible {
+            return false;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        true
+    }
+}
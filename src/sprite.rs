@@ -0,0 +1,344 @@
+use cgmath::{Deg, Matrix3, Rad, Vector2};
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::main_instance::UvTransform;
+
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/**
+    Where and how a sprite is drawn, in pixels with origin in top left corner of the screen.
+    Sprites are drawn by layer from the lowest one, within a layer sprites with greater
+    depth are further away and they are drawn first.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpriteTransform {
+    pub position: [f32; 2],
+    // Point of the sprite put at position and rotated around, (0, 0) is top left corner and (1, 1) bottom right one.
+    pub origin: [f32; 2],
+    pub rotation_degrees: f32,
+    // Scale 1 draws one texel as one pixel.
+    pub scale: [f32; 2],
+    pub layer: i32,
+    pub depth: f32,
+}
+
+impl Default for SpriteTransform {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            origin: [0.0, 0.0],
+            rotation_degrees: 0.0,
+            scale: [1.0, 1.0],
+            layer: 0,
+            depth: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteInstanceRaw {
+    // Rows of 2x3 matrix from unit quad to pixels.
+    pub transform: [[f32; 3]; 2],
+    // Rows of 2x3 matrix from unit quad to texture coordinates, see `main_instance::UvTransform`.
+    pub uv_transform: [[f32; 3]; 2],
+    pub tint: [f32; 4],
+    pub texture_index: i32,
+    pub sampler_index: i32,
+}
+
+impl SpriteInstanceRaw {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x3,
+        3 => Float32x3,
+        4 => Float32x4,
+        5 => Sint32,
+        6 => Sint32,
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteInstanceRaw>() as wgpu::BufferAddress,
+            // Every sprite is one instance of quad made of 6 vertices.
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteUniform {
+    screen_size: [f32; 2],
+    // Uniform buffers have to be aligned to 16 bytes.
+    _padding: [u32; 2],
+}
+
+// Sprite waiting for the end of frame, it is sorted before upload.
+struct QueuedSprite {
+    layer: i32,
+    depth: f32,
+    raw: SpriteInstanceRaw,
+}
+
+/**
+    Draws 2D sprites cut out of scene textures with a screen space orthographic projection,
+    on top of the tone mapped scene and below text.
+
+    Uses textures and samplers bound for main pass, so sprites are sampled the same way as
+    instances. Works the same way as `text::TextRenderer`: sprites are collected during the frame,
+    `prepare` sorts and uploads them and they are gone after that. All of them are drawn
+    with alpha blending in a single draw call, each with its own texture layer and sampler.
+
+    Besides sprites of the scene, sprites can be added every frame by the callback given to
+    `run_with_sprites` or `render_headless_with_sprites`.
+*/
+pub struct SpriteBatch {
+    sprites: Vec<QueuedSprite>,
+    instance_buffer: wgpu::Buffer,
+    // Number of sprites the buffer can hold.
+    capacity: u64,
+    // Number of sprites uploaded by last `prepare`.
+    sprite_count: u32,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    // Size of texture layers in texels.
+    texture_size: [f32; 2],
+    // Sampler of sprites added by `draw_sprite`.
+    sampler_index: i32,
+    // Scene `sprite_sampler`, the sampler is reset to it at the start of every frame.
+    default_sampler_index: i32,
+}
+
+impl SpriteBatch {
+    const INITIAL_CAPACITY: u64 = 64;
+
+    /**
+        `texture_bind_group_layout` and `shader` are the ones of main pass,
+        shader is made by `main_bind_group::create_sprite_shader`.
+    */
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        texture_size: [u32; 2],
+        sampler_index: u32,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Sprite Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[SpriteUniform {
+                    screen_size: [1.0, 1.0],
+                    _padding: [0; 2],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("sprite_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("sprite_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Sprite Pipeline Layout"),
+                bind_group_layouts: &[
+                    texture_bind_group_layout,
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sprite Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    SpriteInstanceRaw::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Flipped sprites (negative scale) are drawn too.
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // Order of sprites is decided by sorting them, there is no depth buffer.
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            sprites: Vec::new(),
+            instance_buffer: Self::create_instance_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            sprite_count: 0,
+            uniform_buffer,
+            bind_group,
+            pipeline,
+            texture_size: [texture_size[0] as f32, texture_size[1] as f32],
+            sampler_index: sampler_index as i32,
+            default_sampler_index: sampler_index as i32,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Instance Buffer"),
+            size: capacity * std::mem::size_of::<SpriteInstanceRaw>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /**
+        Sets sampler (index into scene samplers) of sprites added after this call, indices past
+        the last sampler use the last one.
+    */
+    pub fn set_sampler(&mut self, sampler: u32) {
+        self.sampler_index = sampler as i32;
+    }
+
+    /**
+        Adds sprite showing `rect` (x, y, width and height in texels) of texture layer,
+        whole layer when there is no rect. Tint multiplies texture colour.
+
+        Sprites that are not rotated are moved to whole pixels, with nearest sampler
+        and whole number scale every texel then covers exactly `scale` pixels.
+    */
+    pub fn draw_sprite(&mut self, texture: u32, rect: Option<[f32; 4]>, transform: &SpriteTransform, tint: [f32; 4]) {
+        let [x, y, width, height] = rect.unwrap_or([0.0, 0.0, self.texture_size[0], self.texture_size[1]]);
+        let uv_transform = UvTransform {
+            offset: [x / self.texture_size[0], y / self.texture_size[1]],
+            scale: [width / self.texture_size[0], height / self.texture_size[1]],
+            ..UvTransform::default()
+        };
+
+        let size = Vector2::new(width * transform.scale[0], height * transform.scale[1]);
+        let pivot = Vector2::new(transform.origin[0] * size.x, transform.origin[1] * size.y);
+        let mut matrix = Matrix3::from_translation(Vector2::from(transform.position))
+            * Matrix3::from_angle_z(Rad::from(Deg(transform.rotation_degrees)))
+            * Matrix3::from_translation(-pivot)
+            * Matrix3::from_nonuniform_scale(size.x, size.y);
+        if transform.rotation_degrees == 0.0 {
+            matrix.z.x = matrix.z.x.round();
+            matrix.z.y = matrix.z.y.round();
+        }
+
+        self.sprites.push(QueuedSprite {
+            layer: transform.layer,
+            depth: transform.depth,
+            raw: SpriteInstanceRaw {
+                // cgmath matrices are column major.
+                transform: [
+                    [matrix.x.x, matrix.y.x, matrix.z.x],
+                    [matrix.x.y, matrix.y.y, matrix.z.y],
+                ],
+                uv_transform: uv_transform.to_raw(),
+                tint,
+                texture_index: texture as i32,
+                sampler_index: self.sampler_index,
+            },
+        });
+    }
+
+    /**
+        Sorts sprites collected since last call back to front and uploads them
+        together with size of the frame they are drawn to.
+    */
+    pub(crate) fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, screen_size: [f32; 2]) {
+        // Sort is stable so sprites with the same layer and depth keep the order they were added in.
+        self.sprites.sort_by(|a, b| a.layer.cmp(&b.layer).then(b.depth.total_cmp(&a.depth)));
+        let needed = self.sprites.len() as u64;
+        if needed > self.capacity {
+            self.capacity = needed.next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.capacity);
+        }
+        if !self.sprites.is_empty() {
+            let raw = self.sprites.iter().map(|sprite| sprite.raw).collect::<Vec<_>>();
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw));
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[SpriteUniform {
+            screen_size,
+            _padding: [0; 2],
+        }]));
+        self.sprite_count = self.sprites.len() as u32;
+        self.sprites.clear();
+        self.sampler_index = self.default_sampler_index;
+    }
+
+    // Returns number of drawn sprites, there is no pass when there are no sprites.
+    pub(crate) fn build_render_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        texture_bind_group: &wgpu::BindGroup,
+    ) -> u32 {
+        if self.sprite_count == 0 {
+            return 0;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Sprite Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, texture_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.sprite_count);
+        self.sprite_count
+    }
+}
@@ -0,0 +1,322 @@
+use cgmath::{Matrix4, Point3, Vector4};
+use wgpu::util::DeviceExt;
+
+use crate::error::RendererError;
+use crate::tx::TextureWrapper;
+
+// Font atlas has printable ASCII characters (from space to ~) in 16 columns and 6 rows,
+// it was rendered from DejaVu Sans Mono.
+const FONT_ATLAS: &[u8] = include_bytes!("assets/font.png");
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 6;
+const FIRST_CHARACTER: u8 = b' ';
+// Character drawn instead of the ones that are not in the atlas.
+const UNKNOWN_CHARACTER: u8 = b'?';
+
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GlyphInstanceRaw {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl GlyphInstanceRaw {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x2,
+        4 => Float32x4,
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GlyphInstanceRaw>() as wgpu::BufferAddress,
+            // Every glyph is one instance of quad made of 6 vertices.
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUniform {
+    size: [f32; 2],
+    // Uniform buffers have to be aligned to 16 bytes.
+    _padding: [f32; 2],
+}
+
+/**
+    Draws text on top of everything else, both overlays placed in screen pixels
+    and labels attached to points in the world.
+
+    Works the same way as `debug_draw::DebugDraw`: text is collected during the frame,
+    `prepare` uploads it and text is gone after that.
+*/
+pub struct TextRenderer {
+    glyphs: Vec<GlyphInstanceRaw>,
+    instance_buffer: wgpu::Buffer,
+    // Number of glyphs the buffer can hold.
+    capacity: u64,
+    // Number of glyphs uploaded by last `prepare`.
+    glyph_count: u32,
+    screen_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    // Size of glyph in the atlas in pixels, text of scale 1.0 is drawn with this size.
+    glyph_size: [f32; 2],
+}
+
+impl TextRenderer {
+    const INITIAL_CAPACITY: u64 = 256;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Result<Self, RendererError> {
+        let atlas_image = image::load_from_memory(FONT_ATLAS).map_err(RendererError::FontAtlas)?;
+        let glyph_size = [
+            (atlas_image.width() / ATLAS_COLUMNS) as f32,
+            (atlas_image.height() / ATLAS_ROWS) as f32,
+        ];
+        let atlas = TextureWrapper::from_image(device, queue, atlas_image, Some("font_atlas"))?;
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let screen_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Screen Buffer"),
+                contents: bytemuck::cast_slice(&[ScreenUniform { size: [1.0, 1.0], _padding: [0.0; 2] }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("text_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: screen_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("text_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/text.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Text Pipeline Layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Text Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    GlyphInstanceRaw::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // Text is drawn in its own pass after everything else, straight to the frame.
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Ok(Self {
+            glyphs: Vec::new(),
+            instance_buffer: Self::create_instance_buffer(device, Self::INITIAL_CAPACITY),
+            capacity: Self::INITIAL_CAPACITY,
+            glyph_count: 0,
+            screen_buffer,
+            bind_group,
+            pipeline,
+            glyph_size,
+        })
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Glyph Instance Buffer"),
+            size: capacity * std::mem::size_of::<GlyphInstanceRaw>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /**
+        Adds text with its top left corner at given position in pixels.
+        Scale 1.0 draws glyphs in the size they have in the atlas, text can have multiple lines.
+    */
+    pub fn text(&mut self, position: [f32; 2], scale: f32, text: &str, color: [f32; 4]) {
+        let size = [self.glyph_size[0] * scale, self.glyph_size[1] * scale];
+        let mut cursor = position;
+        for character in text.bytes() {
+            if character == b'\n' {
+                cursor = [position[0], cursor[1] + size[1]];
+                continue;
+            }
+            if character != b' ' {
+                let (uv_min, uv_max) = Self::atlas_uv(character);
+                self.glyphs.push(GlyphInstanceRaw { position: cursor, size, uv_min, uv_max, color });
+            }
+            cursor[0] += size[0];
+        }
+    }
+
+    /**
+        Adds text centered above point in the world. Label keeps its size no matter how far
+        the point is, it is not drawn when point is behind the camera.
+    */
+    pub fn label(
+        &mut self,
+        world_position: Point3<f32>,
+        view_proj: Matrix4<f32>,
+        screen_size: [f32; 2],
+        text: &str,
+        color: [f32; 4],
+    ) {
+        let clip = view_proj * Vector4::new(world_position.x, world_position.y, world_position.z, 1.0);
+        if clip.w <= 0.0 {
+            return;
+        }
+        let scale = 0.5;
+        let width = text.lines().map(str::len).max().unwrap_or(0) as f32 * self.glyph_size[0] * scale;
+        let height = self.glyph_size[1] * scale;
+        let x = (clip.x / clip.w + 1.0) / 2.0 * screen_size[0];
+        let y = (1.0 - clip.y / clip.w) / 2.0 * screen_size[1];
+        self.text([x - width / 2.0, y - height], scale, text, color);
+    }
+
+    fn atlas_uv(character: u8) -> ([f32; 2], [f32; 2]) {
+        let character = if (FIRST_CHARACTER..FIRST_CHARACTER + (ATLAS_COLUMNS * ATLAS_ROWS) as u8).contains(&character) {
+            character
+        } else {
+            UNKNOWN_CHARACTER
+        };
+        let index = (character - FIRST_CHARACTER) as u32;
+        let column = (index % ATLAS_COLUMNS) as f32;
+        let row = (index / ATLAS_COLUMNS) as f32;
+        let cell = [1.0 / ATLAS_COLUMNS as f32, 1.0 / ATLAS_ROWS as f32];
+        (
+            [column * cell[0], row * cell[1]],
+            [(column + 1.0) * cell[0], (row + 1.0) * cell[1]],
+        )
+    }
+
+    /**
+        Uploads text collected since last call together with size of the frame it is drawn to.
+    */
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, screen_size: [f32; 2]) {
+        let needed = self.glyphs.len() as u64;
+        if needed > self.capacity {
+            self.capacity = needed.next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.capacity);
+        }
+        if !self.glyphs.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.glyphs));
+        }
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[ScreenUniform { size: screen_size, _padding: [0.0; 2] }]));
+        self.glyph_count = self.glyphs.len() as u32;
+        self.glyphs.clear();
+    }
+
+    // Returns number of drawn glyphs, there is no pass when there is no text.
+    pub fn build_render_pass(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> u32 {
+        if self.glyph_count == 0 {
+            return 0;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Text Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.glyph_count);
+        self.glyph_count
+    }
+}
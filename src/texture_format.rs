@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::RendererError;
+
+// Features needed by `TextureFormat::R16Unorm`.
+pub const R16_UNORM_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_FORMAT_16BIT_NORM;
+
+/**
+    What texels of a texture mean and how they are stored, chosen per texture instead of
+    assuming every image is 8 bit sRGB colour. Every format knows its wgpu format and how
+    to convert decoded image to bytes of that format.
+
+    All of them are filterable, so they can be sampled by the linear sampler of main shader.
+    Channels missing in the format read as 0 (green, blue) and 1 (alpha) in shaders.
+    Layers of one texture share a format, layers of different formats are stored as `Hdr`,
+    which can hold texels of all of them except full precision of `R16Unorm` (see `common`).
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TextureFormat {
+    // My understanding is that this format in part tells us what is layout of bytes in actual texture data
+    // and in part how shader interprets it.
+    //
+    // Rgba8UnormSrgb means that
+    // - in texture data there are 4 channels (rgba) each has 8 bits which means that each pixel has 32 bits
+    // - color data is normalized in shader to range (0, 1) so that if you access (for instance sample) texture
+    //      in shader then you pick values from this range instead of exact color values
+    // - Srgb refers to sRGB color space.
+    //      This setting results is some transformation (gamma correction) done by graphic card on the shader.
+    //      It has to do with the fact that our eyes can distinguish colors
+    //      better when they are dark so I guess there is bigger color range
+    //      assigned to dark color and bright colors got compressed together because it does not matter.
+    //      Note that Srgb formats are not supported by texture storage array.
+    #[default]
+    Srgb,
+    // 8 bit rgba read as it is, for data like normal maps that must not be gamma decoded.
+    Linear,
+    // Red (or grey) channel only, for roughness, metalness or ambient occlusion maps.
+    R8,
+    // Red and green channels, for normal maps that store only x and y.
+    Rg8,
+    // Red (or grey) channel with all 16 bits, for heightfields saved as 16 bit greyscale.
+    // Needs `R16_UNORM_FEATURES`, there is no format with the same precision without them.
+    R16Unorm,
+    // Linear colour as 16 bit floats, values can go above 1.
+    // Float images (Radiance .hdr, OpenEXR .exr) are used as they are, 8 and 16 bit ones are converted from sRGB.
+    Hdr,
+}
+
+impl TextureFormat {
+    pub fn wgpu_format(self) -> wgpu::TextureFormat {
+        match self {
+            TextureFormat::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Linear => wgpu::TextureFormat::Rgba8Unorm,
+            TextureFormat::R8 => wgpu::TextureFormat::R8Unorm,
+            TextureFormat::Rg8 => wgpu::TextureFormat::Rg8Unorm,
+            TextureFormat::R16Unorm => wgpu::TextureFormat::R16Unorm,
+            TextureFormat::Hdr => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+
+    pub fn bytes_per_texel(self) -> u32 {
+        match self {
+            TextureFormat::R8 => 1,
+            TextureFormat::Rg8 | TextureFormat::R16Unorm => 2,
+            TextureFormat::Srgb | TextureFormat::Linear => 4,
+            TextureFormat::Hdr => 8,
+        }
+    }
+
+    // Features device needs to have for textures of this format.
+    pub fn required_features(self) -> wgpu::Features {
+        match self {
+            TextureFormat::R16Unorm => R16_UNORM_FEATURES,
+            _ => wgpu::Features::empty(),
+        }
+    }
+
+    // Format used when none is given: HDR for HDR image, sRGB colour otherwise.
+    pub fn for_image(image: &image::DynamicImage) -> Self {
+        Self::for_images(std::slice::from_ref(image))
+    }
+
+    // Like `for_image`, HDR when any of the images is HDR.
+    pub fn for_images(images: &[image::DynamicImage]) -> Self {
+        if images.iter().any(is_hdr_image) {
+            TextureFormat::Hdr
+        } else {
+            TextureFormat::Srgb
+        }
+    }
+
+    /**
+        Format layers of given formats are stored in: the format they all have, or HDR when they differ.
+        `R16Unorm` layers can't be mixed with others, half floats of HDR have only 11 bits of precision.
+    */
+    pub fn common(formats: &[Self]) -> Result<Self, RendererError> {
+        match formats.split_first() {
+            Some((first, rest)) if rest.iter().all(|format| format == first) => Ok(*first),
+            Some(_) => match formats.iter().position(|format| *format == TextureFormat::R16Unorm) {
+                Some(layer) => Err(RendererError::MixedTextureFormats { layer, format: TextureFormat::R16Unorm }),
+                None => Ok(TextureFormat::Hdr),
+            },
+            None => Ok(TextureFormat::default()),
+        }
+    }
+
+    /**
+        Texels of the image in `storage` format, rows are tightly packed. When `storage` is not
+        this format, it is HDR and texels are converted so they read the same in shaders.
+    */
+    pub fn texels_in(self, storage: Self, image: &image::DynamicImage) -> Vec<u8> {
+        if storage == self {
+            return self.texels(image);
+        }
+        debug_assert_eq!(storage, TextureFormat::Hdr, "Layers of different formats are stored as HDR");
+        self.hdr_texels(image)
+    }
+
+    // Texels of the image in this format, rows are tightly packed.
+    pub fn texels(self, image: &image::DynamicImage) -> Vec<u8> {
+        match self {
+            TextureFormat::Srgb | TextureFormat::Linear => image.to_rgba8().into_raw(),
+            // Grey images are expanded to rgb, so red channel is the grey value for them too.
+            TextureFormat::R8 => image.to_rgba8().pixels().map(|pixel| pixel.0[0]).collect(),
+            TextureFormat::Rg8 => image.to_rgba8().pixels().flat_map(|pixel| [pixel.0[0], pixel.0[1]]).collect(),
+            TextureFormat::R16Unorm => {
+                let reds = image.to_rgba16().pixels().map(|pixel| pixel.0[0]).collect::<Vec<_>>();
+                bytemuck::cast_slice(&reds).to_vec()
+            }
+            TextureFormat::Hdr => self.hdr_texels(image),
+        }
+    }
+
+    // Texels as linear 16 bit float rgba that read in shaders the same as texels of this format.
+    fn hdr_texels(self, image: &image::DynamicImage) -> Vec<u8> {
+        let hdr = is_hdr_image(image);
+        let halves = image.to_rgba32f().pixels()
+            .flat_map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                match self {
+                    // 8 and 16 bit images in HDR format are sRGB colour too.
+                    TextureFormat::Srgb | TextureFormat::Hdr if !hdr => {
+                        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+                    }
+                    TextureFormat::R8 | TextureFormat::R16Unorm => [r, 0.0, 0.0, 1.0],
+                    TextureFormat::Rg8 => [r, g, 0.0, 1.0],
+                    _ => [r, g, b, a],
+                }
+            })
+            .map(f32_to_f16_bits)
+            .collect::<Vec<_>>();
+        bytemuck::cast_slice(&halves).to_vec()
+    }
+}
+
+// Images with float channels, the only ones that can hold values above 1.
+pub fn is_hdr_image(image: &image::DynamicImage) -> bool {
+    matches!(image.color(), image::ColorType::Rgb32F | image::ColorType::Rgba32F)
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// IEEE 754 half precision float nearest to the value, ties to even.
+fn f32_to_f16_bits(value: f32) -> u16 {
+    half::f16::from_f32(value).to_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn halves(bytes: &[u8]) -> Vec<u16> {
+        bytemuck::pod_collect_to_vec(bytes)
+    }
+
+    #[test]
+    fn layers_of_different_formats_are_stored_as_hdr() {
+        assert_eq!(TextureFormat::common(&[TextureFormat::R8, TextureFormat::R8]).unwrap(), TextureFormat::R8);
+        assert_eq!(TextureFormat::common(&[TextureFormat::Srgb, TextureFormat::Linear]).unwrap(), TextureFormat::Hdr);
+
+        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 128, 0, 64])));
+        let one = f32_to_f16_bits(1.0);
+        assert_eq!(halves(&TextureFormat::R8.texels_in(TextureFormat::Hdr, &image)), [one, 0, 0, one]);
+        assert_eq!(halves(&TextureFormat::Rg8.texels_in(TextureFormat::Hdr, &image)), [one, f32_to_f16_bits(128.0 / 255.0), 0, one]);
+        assert_eq!(TextureFormat::Linear.texels_in(TextureFormat::Linear, &image), [255, 128, 0, 64]);
+        // sRGB colour is decoded to linear, the same as sampling Rgba8UnormSrgb gives.
+        let srgb = halves(&TextureFormat::Srgb.texels_in(TextureFormat::Hdr, &image));
+        assert_eq!(srgb[1], f32_to_f16_bits(srgb_to_linear(128.0 / 255.0)));
+    }
+
+    #[test]
+    fn r16_unorm_layers_are_not_mixed_with_others() {
+        assert_eq!(TextureFormat::common(&[TextureFormat::R16Unorm; 2]).unwrap(), TextureFormat::R16Unorm);
+        let error = TextureFormat::common(&[TextureFormat::Srgb, TextureFormat::R16Unorm, TextureFormat::R8]).unwrap_err();
+        assert!(matches!(error, RendererError::MixedTextureFormats { layer: 1, format: TextureFormat::R16Unorm }));
+    }
+
+    #[test]
+    fn r16_unorm_keeps_all_bits() {
+        let image = image::DynamicImage::ImageLuma16(image::ImageBuffer::from_raw(3, 1, vec![0, 0x1234, 0xffff]).unwrap());
+        assert_eq!(halves(&TextureFormat::R16Unorm.texels(&image)), [0, 0x1234, 0xffff]);
+    }
+
+    #[test]
+    fn halves_are_rounded_to_nearest_even() {
+        assert_eq!(f32_to_f16_bits(1.0), 0x3c00);
+        // Halfway between 1.0 and the next half goes down to even mantissa, the next halfway one goes up.
+        assert_eq!(f32_to_f16_bits(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16_bits(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        assert_eq!(f32_to_f16_bits(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16_bits(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16_bits(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16_bits(2f32.powi(-26)), 0x0000);
+        assert_eq!(f32_to_f16_bits(-2f32.powi(-14)), 0x8400);
+        assert_eq!(f32_to_f16_bits(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16_bits(f32::NEG_INFINITY), 0xfc00);
+        let nan = f32_to_f16_bits(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x3ff, 0);
+    }
+}
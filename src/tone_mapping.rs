@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::{tx, vertex};
+
+/**
+    How linear HDR colours of main pass are brought to range of the frame.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ToneMapping {
+    // Values above 1 are clipped, LDR scenes look the same as without HDR target.
+    #[default]
+    Clamp,
+    // c / (1 + c), keeps hue but washes out highlights.
+    Reinhard,
+    // Filmic curve of ACES fitted by Krzysztof Narkowicz.
+    Aces,
+    // Minimal AgX approximation, desaturates bright colours towards white like film.
+    Agx,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 4] = [
+        ToneMapping::Clamp,
+        ToneMapping::Reinhard,
+        ToneMapping::Aces,
+        ToneMapping::Agx,
+    ];
+
+    // Has to match curve constants in tone_mapping.wgsl.
+    fn shader_index(self) -> u32 {
+        match self {
+            ToneMapping::Clamp => 0,
+            ToneMapping::Reinhard => 1,
+            ToneMapping::Aces => 2,
+            ToneMapping::Agx => 3,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMappingUniform {
+    exposure_scale: f32,
+    // Exponent applied to tone mapped colour, it already accounts for sRGB encoding of the frame.
+    output_exponent: f32,
+    curve: u32,
+    _padding: u32,
+}
+
+const WHOLE_SCREEN_VERTICES: &[vertex::Vertex] = &[
+    vertex::Vertex {
+        position: [-1.0, -1.0, 0.0],
+        tex_coords: [0.0, 1.0],
+    },
+    vertex::Vertex {
+        position: [1.0, -1.0, 0.0],
+        tex_coords: [1.0, 1.0],
+    },
+    vertex::Vertex {
+        position: [1.0, 1.0, 0.0],
+        tex_coords: [1.0, 0.0],
+    },
+    vertex::Vertex {
+        position: [-1.0, 1.0, 0.0],
+        tex_coords: [0.0, 0.0],
+    },
+];
+
+const WHOLE_SCREEN_INDICES: &[u16] = &[
+    0, 1, 2, 0, 2, 3
+];
+
+/**
+    Main pass renders to HDR target (`TextureWrapper::HDR_FORMAT`), this pass scales it by
+    exposure, tone maps it and applies gamma while copying it to the frame.
+    Everything drawn after it (depth visualisation, text, ui) goes straight to the frame.
+*/
+pub struct ToneMappingPass {
+    pub hdr_target: tx::TextureWrapper,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    // sRGB frame encodes colours itself, gamma only corrects what differs from it.
+    srgb_output: bool,
+    pub tone_mapping: ToneMapping,
+    pub exposure: f32,
+    pub gamma: f32,
+}
+
+impl ToneMappingPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        tone_mapping: ToneMapping,
+        exposure: f32,
+        gamma: f32,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Tone Mapping Vertex Buffer"),
+                contents: bytemuck::cast_slice(WHOLE_SCREEN_VERTICES),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Tone Mapping Index Buffer"),
+                contents: bytemuck::cast_slice(WHOLE_SCREEN_INDICES),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        let num_indices = WHOLE_SCREEN_INDICES.len() as u32;
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tone Mapping Buffer"),
+            size: std::mem::size_of::<ToneMappingUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // HDR target has the same size as the frame, every pixel is read exactly.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("tone_mapping_bind_group_layout"),
+        });
+
+        let hdr_target = tx::TextureWrapper::create_hdr_target(device, config);
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &hdr_target, &sampler, &uniform_buffer);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/tone_mapping.wgsl"));
+        let render_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Tone Mapping Pipeline Layout"),
+                bind_group_layouts: &[
+                    &bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tone Mapping Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    vertex::Vertex::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            hdr_target,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            uniform_buffer,
+            pipeline,
+            vertex_buffer,
+            index_buffer,
+            num_indices,
+            srgb_output: config.format.is_srgb(),
+            tone_mapping,
+            exposure,
+            gamma,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        hdr_target: &tx::TextureWrapper,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&hdr_target.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("tone_mapping_bind_group"),
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        self.hdr_target = tx::TextureWrapper::create_hdr_target(device, config);
+        self.bind_group = Self::create_bind_group(
+            device, &self.bind_group_layout, &self.hdr_target, &self.sampler, &self.uniform_buffer,
+        );
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue) {
+        // sRGB frame applies encoding close to gamma 2.2 on its own.
+        let output_exponent = if self.srgb_output { 2.2 / self.gamma } else { 1.0 / self.gamma };
+        let uniform = ToneMappingUniform {
+            exposure_scale: self.exposure.exp2(),
+            output_exponent,
+            curve: self.tone_mapping.shader_index(),
+            _padding: 0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn build_render_pass(&self, encoder: &mut wgpu::CommandEncoder, texture_view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tone Mapping Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // Every pixel is overwritten.
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
@@ -1,6 +1,7 @@
-use anyhow::*;
 use image::GenericImageView;
-use crate::globals;
+use crate::compressed_texture::{self, CompressedTexture};
+use crate::error::RendererError;
+use crate::texture_format::{self, TextureFormat};
 
 pub struct TextureWrapper {
     pub texture: wgpu::Texture,
@@ -9,16 +10,72 @@ pub struct TextureWrapper {
 
 impl TextureWrapper {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // Format of the colour target of main pass, same as the one of `TextureFormat::Hdr`.
+    // Values are linear and can go above 1, tone mapping pass brings them to the frame.
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    /**
+        Each of `bytes` is either an image, which is one layer, or KTX2/DDS container, each layer
+        of which is a layer of the array. `formats` are their formats, layers without format get
+        the one of `TextureFormat::for_image`.
+        The array can have more layers than `bytes`, see `padded_layer_count`.
+
+        When all layers are containers with the same format and number of mip levels their blocks
+        are used as they are and `formats` are ignored, otherwise containers are decompressed
+        and converted together with the images.
+    */
     pub fn multilayer_from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[&[u8]],
+        formats: &[Option<TextureFormat>],
         label: &str,
-    ) -> Result<Self> {
-        let images: Vec<image::DynamicImage> = bytes.iter()
-            .map(|b| image::load_from_memory(*b).unwrap())
-            .collect();
-        Self::multilayer_from_images(device, queue, &images, Some(label))
+    ) -> Result<Self, RendererError> {
+        let compressed = bytes.iter()
+            .enumerate()
+            .map(|(index, b)| compressed_texture::is_container(b)
+                .then(|| CompressedTexture::parse(b).map_err(|source| RendererError::CompressedTexture { index, source }))
+                .transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(mut layered) = stack_layers(&compressed) {
+            let layers = padded_layer_count(layered.layers);
+            for level in &mut layered.levels {
+                let layer_bytes = level.len() / layered.layers as usize;
+                level.resize(layer_bytes * layers as usize, 0);
+            }
+            layered.layers = layers;
+            if formats.iter().any(Option::is_some) {
+                log::warn!("Compressed textures keep their own format, formats of scene textures are ignored");
+            }
+            return Self::from_compressed(device, queue, &layered, wgpu::TextureViewDimension::D2Array, Some(label));
+        }
+        if !compressed.is_empty() && compressed.iter().all(Option::is_some) {
+            log::warn!("Compressed textures differ in size, format or mip levels, they are decompressed");
+        }
+
+        // Every layer of a container is a layer of the array, with the format given for the container.
+        let mut images = Vec::new();
+        let mut layer_formats = Vec::new();
+        for (index, (b, compressed)) in bytes.iter().zip(&compressed).enumerate() {
+            let layers = match compressed {
+                Some(compressed) => compressed.to_images(),
+                None => vec![decode_image(b).map_err(|source| RendererError::ImageDecode { index, source })?],
+            };
+            let format = formats.get(index).copied().flatten();
+            layer_formats.extend(layers.iter().map(|image| format.unwrap_or_else(|| TextureFormat::for_image(image))));
+            images.extend(layers);
+        }
+        let mut formats = layer_formats;
+        let storage = TextureFormat::common(&formats)?;
+        // Layers of different formats are stored as HDR, so only a format all of them have can be missing.
+        if !device.features().contains(storage.required_features()) {
+            return Err(RendererError::UnsupportedTextureFormat { index: 0, format: storage });
+        }
+        if let Some(first) = images.first() {
+            let blank = image::DynamicImage::new_rgba8(first.width(), first.height());
+            images.resize(padded_layer_count(images.len() as u32) as usize, blank);
+            formats.resize(images.len(), storage);
+        }
+        Self::multilayer_from_images(device, queue, &images, &formats, Some(label))
     }
 
     /**
@@ -26,23 +83,51 @@ impl TextureWrapper {
         Such textures are useful to bind them to texture_2d_array slot in shader so that it is
         possible to dynamically choose which texture to render in shader.
 
-        Each individual texture is separate layer.
-        Limitation of this approach is that all images need to have same dimensions.
+        Each individual texture is separate layer, `formats` are formats of the images.
+        Limitation of this approach is that all images need to have same dimensions
+        and all of them are stored in the same format, see `TextureFormat::common`.
     */
     pub fn multilayer_from_images(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         images: &[image::DynamicImage],
+        formats: &[TextureFormat],
         label: Option<&str>,
-    ) -> Result<Self> {
+    ) -> Result<Self, RendererError> {
+        let format = TextureFormat::common(formats)?;
+        if format == TextureFormat::Hdr && formats.iter().any(|layer| *layer != TextureFormat::Hdr) {
+            log::warn!("Texture layers have different formats {:?}, they are stored as Hdr", formats);
+        }
+        let base_dimensions = images.first().ok_or(RendererError::NoImages)?.dimensions();
+        if let Some((layer, image)) = images.iter().enumerate().find(|(_, i)| i.dimensions() != base_dimensions) {
+            return Err(RendererError::DimensionMismatch {
+                layer,
+                expected: base_dimensions,
+                actual: image.dimensions(),
+            });
+        }
 
-        assert!(images.len() > 0, "Trying to create layered texture with no images");
-        let base_dimensions = images[0].dimensions();
+        let limits = device.limits();
+        let max_side = base_dimensions.0.max(base_dimensions.1);
+        if max_side > limits.max_texture_dimension_2d {
+            return Err(RendererError::LimitExceeded {
+                what: "texture size",
+                value: max_side,
+                limit: limits.max_texture_dimension_2d,
+            });
+        }
+        if images.len() as u32 > limits.max_texture_array_layers {
+            return Err(RendererError::LimitExceeded {
+                what: "number of texture layers",
+                value: images.len() as u32,
+                limit: limits.max_texture_array_layers,
+            });
+        }
 
         let total_tx_size = wgpu::Extent3d {
             width: base_dimensions.0,
             height: base_dimensions.1,
-            depth_or_array_layers: 2,
+            depth_or_array_layers: images.len() as u32,
         };
         let single_layer_size = wgpu::Extent3d {
             width: base_dimensions.0,
@@ -56,19 +141,15 @@ impl TextureWrapper {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: globals::TEXTURE_FORMAT,
+                format: format.wgpu_format(),
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             }
         );
 
-        for image_idx in 0..images.len() {
-            let image = &images[image_idx];
+        for (image_idx, (image, image_format)) in images.iter().zip(formats).enumerate() {
 
-            // This has to match texture format
-            // todo: remove this assumption, chosen format should have assigned function to choose data from image
-            let rgba_data = image.to_rgba8();
-            assert_eq!(base_dimensions, image.dimensions(), "Dimension of each image of layered textures must be the same!");
+            let texels = image_format.texels_in(format, image);
             queue.write_texture(
                 wgpu::ImageCopyTexture {
                     aspect: wgpu::TextureAspect::All,
@@ -77,10 +158,10 @@ impl TextureWrapper {
                     // z coordinate here is layer
                     origin: wgpu::Origin3d { x: 0, y: 0, z: image_idx as u32 },
                 },
-                &rgba_data,
+                &texels,
                 wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(4 * base_dimensions.0),
+                    bytes_per_row: Some(format.bytes_per_texel() * base_dimensions.0),
                     rows_per_image: Some(base_dimensions.1),
                 },
                 single_layer_size,
@@ -89,7 +170,7 @@ impl TextureWrapper {
         }
 
         let view_descriptor = wgpu::TextureViewDescriptor {
-            format: Some(globals::TEXTURE_FORMAT),
+            format: Some(format.wgpu_format()),
             dimension: Some(wgpu::TextureViewDimension::D2Array),
             ..Default::default()
         };
@@ -98,6 +179,192 @@ impl TextureWrapper {
         Ok(Self { texture, view })
     }
 
+    /**
+        Texture with single image, viewed as plain texture_2d.
+
+        GL backend creates texture with one layer as 2D texture, not as an array,
+        so such texture can't be bound as texture_2d_array there.
+    */
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: image::DynamicImage,
+        label: Option<&str>,
+    ) -> Result<Self, RendererError> {
+        let format = TextureFormat::for_image(&image);
+        let mut wrapper = Self::multilayer_from_images(device, queue, &[image], &[format], label)?;
+        wrapper.view = wrapper.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(wrapper.texture.format()),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            ..Default::default()
+        });
+        Ok(wrapper)
+    }
+
+    /**
+        Cube map made of six square faces in order +x, -x, +y, -y, +z, -z, viewed as texture_cube.
+        Faces are oriented the same way as in Vulkan and OpenGL, see `equirectangular_to_cube_faces`.
+    */
+    pub fn cube_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: Option<&str>,
+    ) -> Result<Self, RendererError> {
+        if faces.len() != 6 {
+            return Err(RendererError::CubeFaces(format!("cube map needs 6 faces, got {}", faces.len())));
+        }
+        let (width, height) = faces[0].dimensions();
+        if width != height {
+            return Err(RendererError::CubeFaces(format!("faces have to be square, they are {}x{}", width, height)));
+        }
+        // Six square layers also make GL backend create the texture as a cube map.
+        let format = TextureFormat::for_images(faces);
+        let mut wrapper = Self::multilayer_from_images(device, queue, faces, &[format; 6], label)?;
+        wrapper.view = wrapper.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(wrapper.texture.format()),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        Ok(wrapper)
+    }
+
+    /**
+        Texture with all layers and mip levels of KTX2 or DDS container.
+
+        Blocks are uploaded as they are when device has `compressed_texture::COMPRESSION_FEATURES`,
+        otherwise they are decompressed on CPU to `CompressedTexture::decompressed_format`.
+        Size of compressed texture on GPU has to be multiple of block size, other sizes are decompressed too.
+        Formats without wgpu equivalent (`BlockFormat::wgpu_format`) are always decompressed.
+    */
+    pub fn from_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        compressed: &CompressedTexture,
+        dimension: wgpu::TextureViewDimension,
+        label: Option<&str>,
+    ) -> Result<Self, RendererError> {
+        let limits = device.limits();
+        let max_side = compressed.width.max(compressed.height);
+        if max_side > limits.max_texture_dimension_2d {
+            return Err(RendererError::LimitExceeded {
+                what: "texture size",
+                value: max_side,
+                limit: limits.max_texture_dimension_2d,
+            });
+        }
+        if compressed.layers > limits.max_texture_array_layers {
+            return Err(RendererError::LimitExceeded {
+                what: "number of texture layers",
+                value: compressed.layers,
+                limit: limits.max_texture_array_layers,
+            });
+        }
+
+        let native = compressed.format.wgpu_format(compressed.srgb).filter(|_| {
+            device.features().contains(compressed_texture::COMPRESSION_FEATURES)
+                && compressed.width % 4 == 0
+                && compressed.height % 4 == 0
+        });
+        let decompressed;
+        // Levels are copied in units of blocks, decompressed texels are blocks of 1x1.
+        let (format, levels, block_size, block_bytes) = if let Some(format) = native {
+            (format, &compressed.levels, 4, compressed.format.block_bytes() as u32)
+        } else {
+            log::info!(
+                "Decompressing {:?} texture {} on CPU",
+                compressed.format,
+                label.unwrap_or_default(),
+            );
+            decompressed = compressed.decompress();
+            let format = compressed.decompressed_format();
+            (format.wgpu_format(), &decompressed, 1, format.bytes_per_texel())
+        };
+
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label,
+                size: wgpu::Extent3d {
+                    width: compressed.width,
+                    height: compressed.height,
+                    depth_or_array_layers: compressed.layers,
+                },
+                mip_level_count: levels.len() as u32,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }
+        );
+
+        for (level, data) in levels.iter().enumerate() {
+            let (width, height) = compressed.level_size(level);
+            // Levels smaller than a block still occupy whole block.
+            let (blocks_wide, blocks_high) = (width.div_ceil(block_size), height.div_ceil(block_size));
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(blocks_wide * block_bytes),
+                    rows_per_image: Some(blocks_high),
+                },
+                wgpu::Extent3d {
+                    width: blocks_wide * block_size,
+                    height: blocks_high * block_size,
+                    depth_or_array_layers: compressed.layers,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(format),
+            dimension: Some(dimension),
+            ..Default::default()
+        });
+
+        Ok(Self { texture, view })
+    }
+
+    // Cube map from KTX2 or DDS container with six square layers (faces), see `cube_from_images`.
+    pub fn cube_from_compressed(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        compressed: &CompressedTexture,
+        label: Option<&str>,
+    ) -> Result<Self, RendererError> {
+        if compressed.layers != 6 {
+            return Err(RendererError::CubeFaces(format!("cube map needs 6 faces, got {}", compressed.layers)));
+        }
+        if compressed.width != compressed.height {
+            return Err(RendererError::CubeFaces(
+                format!("faces have to be square, they are {}x{}", compressed.width, compressed.height),
+            ));
+        }
+        Self::from_compressed(device, queue, compressed, wgpu::TextureViewDimension::Cube, label)
+    }
+
+    /**
+        Cube map with faces of `face_size` pixels converted from image in equirectangular projection
+        (longitude goes along width, latitude along height).
+    */
+    pub fn cube_from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::DynamicImage,
+        face_size: u32,
+        label: Option<&str>,
+    ) -> Result<Self, RendererError> {
+        let faces = equirectangular_to_cube_faces(image, face_size);
+        Self::cube_from_images(device, queue, &faces, label)
+    }
+
     // A depth buffer, also known as a z-buffer, is used to implement depth testing in 3D rendering.
     // This technique ensures that closer objects are drawn in front of those
     // that are farther away from the camera.
@@ -115,7 +382,8 @@ impl TextureWrapper {
     // Advanced techniques like early Z-testing/Z-culling can help
     // minimize such overdraw by discarding fragments that would be occluded before running fragment shaders.
     // todo: what is early z-testing / z-culling ?
-    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+    // When depth texture is not `sampleable` it can only be used as render attachment.
+    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32, sampleable: bool, label: &str) -> Self {
         let size = wgpu::Extent3d {
             width: config.width,
             height: config.height,
@@ -125,11 +393,14 @@ impl TextureWrapper {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::TEXTURE_BINDING ,
+            usage: if sampleable {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            },
             view_formats: &[Self::DEPTH_FORMAT],
         };
         let texture = device.create_texture(&desc);
@@ -138,4 +409,205 @@ impl TextureWrapper {
 
         Self { texture, view }
     }
+
+    // Multisampled texture that main pass renders to when MSAA is on.
+    // At the end of the pass it is resolved (samples of each pixel are averaged) into the actual frame.
+    pub fn create_msaa_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("msaa_texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    // Colour target of main pass, sampled by tone mapping pass.
+    pub fn create_hdr_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hdr_target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    // Texture used instead of surface when rendering without window.
+    // It can be copied from so that rendered frames can be read back on CPU.
+    pub fn create_render_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_render_target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+}
+
+/**
+    Number of layers texture array with `layers` textures is created with, extra layers stay black.
+
+    GL backend picks kind of texture by its layer count: with one layer it is a plain 2D texture
+    and with a multiple of six square layers a cube map (array), neither can be bound as texture_2d_array.
+*/
+fn padded_layer_count(layers: u32) -> u32 {
+    let mut padded = layers.max(2);
+    if padded % 6 == 0 {
+        padded += 1;
+    }
+    padded
+}
+
+// Layers of all textures stacked into one, None unless all of them match in size, format and mip levels.
+fn stack_layers(textures: &[Option<CompressedTexture>]) -> Option<CompressedTexture> {
+    let mut layered = textures.first()?.clone()?;
+    for texture in &textures[1..] {
+        let texture = texture.as_ref()?;
+        let same = (texture.width, texture.height, texture.format, texture.srgb, texture.levels.len())
+            == (layered.width, layered.height, layered.format, layered.srgb, layered.levels.len());
+        if !same {
+            return None;
+        }
+        for (level, blocks) in layered.levels.iter_mut().zip(&texture.levels) {
+            level.extend_from_slice(blocks);
+        }
+        layered.layers += texture.layers;
+    }
+    Some(layered)
+}
+
+/**
+    Samples equirectangular image in directions of pixels of cube faces.
+    Face pixel (s, t) in range [-1, 1] (t goes down) is in direction given by the table
+    of cube map faces from the OpenGL specification, so sampling the cube in a world direction
+    gives the same colour as equirectangular image in that direction.
+*/
+fn equirectangular_to_cube_faces(image: &image::DynamicImage, face_size: u32) -> Vec<image::DynamicImage> {
+    use std::f32::consts::PI;
+    let hdr = texture_format::is_hdr_image(image);
+    // HDR values are sampled as they are, 8 bit ones are kept in range [0, 255].
+    let image = match image {
+        image::DynamicImage::ImageRgba32F(image) => image.clone(),
+        _ if hdr => image.to_rgba32f(),
+        _ => image::Rgba32FImage::from_fn(image.width(), image.height(), |x, y| {
+            image::Rgba(image.get_pixel(x, y).0.map(|c| c as f32))
+        }),
+    };
+    let (width, height) = image.dimensions();
+    let directions: [fn(f32, f32) -> [f32; 3]; 6] = [
+        |s, t| [1.0, -t, -s],
+        |s, t| [-1.0, -t, s],
+        |s, t| [s, 1.0, t],
+        |s, t| [s, -1.0, -t],
+        |s, t| [s, -t, 1.0],
+        |s, t| [-s, -t, -1.0],
+    ];
+    // Bilinear sampling, longitude wraps around and latitude is clamped.
+    let sample = |u: f32, v: f32| -> [f32; 4] {
+        let x = u * width as f32 - 0.5;
+        let y = (v * height as f32 - 0.5).clamp(0.0, height as f32 - 1.0);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |x: f32, y: f32| {
+            let x = (x as i64).rem_euclid(width as i64) as u32;
+            let y = (y as u32).min(height - 1);
+            image.get_pixel(x, y).0
+        };
+        let (a, b, c, d) = (pixel(x0, y0), pixel(x0 + 1.0, y0), pixel(x0, y0 + 1.0), pixel(x0 + 1.0, y0 + 1.0));
+        std::array::from_fn(|i| {
+            let top = a[i] + (b[i] - a[i]) * fx;
+            let bottom = c[i] + (d[i] - c[i]) * fx;
+            top + (bottom - top) * fy
+        })
+    };
+    directions.iter()
+        .map(|direction| {
+            let face = image::Rgba32FImage::from_fn(face_size, face_size, |x, y| {
+                let s = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                let t = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                let [dx, dy, dz] = direction(s, t);
+                let longitude = dz.atan2(dx);
+                let latitude = (dy / (dx * dx + dy * dy + dz * dz).sqrt()).asin();
+                image::Rgba(sample((longitude + PI) / (2.0 * PI), 0.5 - latitude / PI))
+            });
+            if hdr {
+                image::DynamicImage::ImageRgba32F(face)
+            } else {
+                image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(face_size, face_size, |x, y| {
+                    image::Rgba(face.get_pixel(x, y).0.map(|c| c.round() as u8))
+                }))
+            }
+        })
+        .collect()
+}
+
+/**
+    Like `image::load_from_memory`, but Radiance .hdr images keep their float values,
+    the generic decoder of image crate converts them to 8 bits. OpenEXR .exr images are
+    decoded to floats by the generic decoder.
+*/
+pub fn decode_image(bytes: &[u8]) -> image::ImageResult<image::DynamicImage> {
+    if image::guess_format(bytes)? != image::ImageFormat::Hdr {
+        return image::load_from_memory(bytes);
+    }
+    let decoder = image::codecs::hdr::HdrDecoder::new(std::io::Cursor::new(bytes))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    let image = image::Rgb32FImage::from_fn(metadata.width, metadata.height, |x, y| {
+        pixels[(y * metadata.width + x) as usize]
+    });
+    Ok(image::DynamicImage::ImageRgb32F(image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exr_images_keep_float_values() {
+        let image = image::Rgba32FImage::from_fn(4, 2, |x, y| image::Rgba([x as f32 * 4.0, y as f32 + 0.25, 100.0, 1.0]));
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgba32F(image.clone()).write_to(&mut bytes, image::ImageFormat::OpenExr).unwrap();
+
+        let decoded = decode_image(bytes.get_ref()).unwrap();
+        assert!(texture_format::is_hdr_image(&decoded));
+        assert_eq!(decoded.to_rgba32f(), image);
+    }
 }
@@ -0,0 +1,308 @@
+use winit::window::Window;
+
+use crate::camera_path::CameraPathPlayer;
+use crate::input::Action;
+use crate::main_instance::MainInstance;
+use crate::render_mode::RenderMode;
+use crate::sampler::SamplerDescription;
+use crate::settings::SettingsEdit;
+use crate::tone_mapping::ToneMapping;
+
+/**
+    Settings panel drawn with egui. It is only available with window, `F1` shows and hides it.
+
+    Events go to egui first, the ones it uses (clicks on the panel, typing into its fields)
+    are not passed to the rest of the application, camera controller in particular.
+*/
+pub struct Ui {
+    context: egui::Context,
+    winit_state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    pub visible: bool,
+    // Output of last `run`, drawn by `build_render_pass`.
+    primitives: Vec<egui::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+}
+
+/**
+    Part of the panel that does not depend on device, it survives device loss.
+*/
+pub struct UiState {
+    visible: bool,
+    // Open headers, position of the window and other egui state of widgets.
+    memory: egui::Memory,
+}
+
+impl Ui {
+    pub fn new(window: &Window, device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let context = egui::Context::default();
+        let mut winit_state = egui_winit::State::new(window);
+        winit_state.set_pixels_per_point(window.scale_factor() as f32);
+        winit_state.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+        Self {
+            context,
+            winit_state,
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1),
+            visible: false,
+            primitives: Vec::new(),
+            textures_delta: Default::default(),
+        }
+    }
+
+    pub fn state(&self) -> UiState {
+        UiState {
+            visible: self.visible,
+            memory: self.context.memory(|memory| memory.clone()),
+        }
+    }
+
+    pub fn restore_state(&mut self, state: UiState) {
+        self.visible = state.visible;
+        self.context.memory_mut(|memory| *memory = state.memory);
+    }
+
+    /**
+        Returns true when event was used by the panel.
+    */
+    pub fn on_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let response = self.winit_state.on_event(&self.context, event);
+        response.consumed
+            || (self.context.wants_keyboard_input() && matches!(event, winit::event::WindowEvent::KeyboardInput { .. }))
+    }
+
+    /**
+        Starts egui frame, nothing is returned while panel is hidden. Widgets are added by running
+        returned context with returned input, its output goes to `end_frame`. Context is returned
+        instead of being run here so that widgets can borrow the rest of the state.
+    */
+    pub fn begin_frame(&mut self, window: &Window) -> Option<(egui::Context, egui::RawInput)> {
+        if !self.visible {
+            self.primitives.clear();
+            return None;
+        }
+        Some((self.context.clone(), self.winit_state.take_egui_input(window)))
+    }
+
+    pub fn end_frame(&mut self, window: &Window, output: egui::FullOutput) {
+        self.winit_state.handle_platform_output(window, &self.context, output.platform_output);
+        self.primitives = self.context.tessellate(output.shapes);
+        self.textures_delta.append(output.textures_delta);
+    }
+
+    /**
+        Draws the panel on top of given frame in its own render pass with egui-wgpu.
+        Returns number of draw calls.
+    */
+    pub fn build_render_pass(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size_in_pixels: [u32; 2],
+    ) -> u32 {
+        let textures_delta = std::mem::take(&mut self.textures_delta);
+        for (id, delta) in &textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let screen = egui_wgpu::renderer::ScreenDescriptor {
+            size_in_pixels,
+            pixels_per_point: self.context.pixels_per_point(),
+        };
+        // Only paint callbacks record their own command buffers, the panel has none.
+        self.renderer.update_buffers(device, queue, encoder, &self.primitives, &screen);
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("UI Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer.render(&mut render_pass, &self.primitives, &screen);
+        }
+        for id in &textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+        self.primitives.iter()
+            .filter(|primitive| matches!(primitive.primitive, egui::epaint::Primitive::Mesh(_)))
+            .count() as u32
+    }
+}
+
+/**
+    Settings shown by the settings panel, borrowed from the state for one frame.
+*/
+pub struct SettingsView<'a> {
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub camera_speed: f32,
+    pub camera_path: &'a CameraPathPlayer,
+    pub render_mode: RenderMode,
+    pub depth_visualisation: bool,
+    pub show_gizmos: bool,
+    pub show_stats: bool,
+    pub tone_mapping: ToneMapping,
+    pub exposure: f32,
+    pub gamma: f32,
+    // Only when there is a skybox to reflect.
+    pub reflectivity: Option<f32>,
+    pub clear_color_cursor_in: wgpu::Color,
+    pub clear_color_cursor_out: wgpu::Color,
+    pub texture_layers: u32,
+    pub samplers: &'a [SamplerDescription],
+    pub instances: &'a [MainInstance],
+}
+
+/**
+    Widgets of the settings panel. They work on copies of the settings and don't change
+    anything, what was changed is pushed to `edits` and applied by `State`.
+*/
+pub fn settings_panel(context: &egui::Context, view: &SettingsView, edits: &mut Vec<SettingsEdit>) {
+    egui::Window::new("Settings").default_width(280.0).show(context, |ui| {
+        egui::CollapsingHeader::new("Camera").default_open(true).show(ui, |ui| {
+            let (mut fovy, mut znear, mut zfar) = (view.fovy, view.znear, view.zfar);
+            if ui.add(egui::Slider::new(&mut fovy, 10.0..=120.0).text("fovy")).changed() {
+                edits.push(SettingsEdit::Fovy(fovy));
+            }
+            if ui.add(egui::Slider::new(&mut znear, 0.01..=10.0).logarithmic(true).text("znear")).changed() {
+                edits.push(SettingsEdit::Znear(znear));
+            }
+            if ui.add(egui::Slider::new(&mut zfar, 1.0..=1000.0).logarithmic(true).text("zfar")).changed() {
+                edits.push(SettingsEdit::Zfar(zfar));
+            }
+            let mut speed = view.camera_speed;
+            if ui.add(egui::Slider::new(&mut speed, 0.01..=2.0).text("speed")).changed() {
+                edits.push(SettingsEdit::CameraSpeed(speed));
+            }
+        });
+
+        egui::CollapsingHeader::new(format!("Camera path ({} keyframes)", view.camera_path.path.keyframes.len())).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let label = if view.camera_path.playing { "pause" } else { "play" };
+                if ui.button(label).clicked() {
+                    edits.push(SettingsEdit::Action(Action::ToggleCameraPath));
+                }
+                if ui.button("add keyframe").clicked() {
+                    edits.push(SettingsEdit::Action(Action::AddCameraKeyframe));
+                }
+                if ui.button("clear").clicked() {
+                    edits.push(SettingsEdit::ClearCameraPath);
+                }
+                if ui.button("save").clicked() {
+                    edits.push(SettingsEdit::Action(Action::SaveCameraPath));
+                }
+            });
+            let mut looping = view.camera_path.path.looping;
+            if ui.checkbox(&mut looping, "loop").changed() {
+                edits.push(SettingsEdit::Action(Action::ToggleCameraPathLoop));
+            }
+            let duration = view.camera_path.path.duration();
+            let mut time = view.camera_path.time;
+            if ui.add(egui::Slider::new(&mut time, 0.0..=duration).text("time")).changed() {
+                edits.push(SettingsEdit::CameraPathTime(time));
+            }
+        });
+
+        egui::CollapsingHeader::new("Rendering").default_open(true).show(ui, |ui| {
+            let mut render_mode = view.render_mode;
+            egui::ComboBox::from_label("render mode")
+                .selected_text(format!("{:?}", render_mode))
+                .show_ui(ui, |ui| {
+                    for mode in RenderMode::ALL {
+                        if ui.selectable_value(&mut render_mode, mode, format!("{:?}", mode)).changed() {
+                            edits.push(SettingsEdit::Action(Action::SetRenderMode(mode)));
+                        }
+                    }
+                });
+            for (mut enabled, label, action) in [
+                (view.depth_visualisation, "depth visualisation", Action::ToggleDepthVisualisation),
+                (view.show_gizmos, "gizmos", Action::ToggleGizmos),
+                (view.show_stats, "stats overlay", Action::ToggleStats),
+            ] {
+                if ui.checkbox(&mut enabled, label).changed() {
+                    edits.push(SettingsEdit::Action(action));
+                }
+            }
+            let mut selected = view.tone_mapping;
+            egui::ComboBox::from_label("tone mapping")
+                .selected_text(format!("{:?}", selected))
+                .show_ui(ui, |ui| {
+                    for tone_mapping in ToneMapping::ALL {
+                        if ui.selectable_value(&mut selected, tone_mapping, format!("{:?}", tone_mapping)).changed() {
+                            edits.push(SettingsEdit::ToneMapping(tone_mapping));
+                        }
+                    }
+                });
+            let mut exposure = view.exposure;
+            if ui.add(egui::Slider::new(&mut exposure, -8.0..=8.0).text("exposure (stops)")).changed() {
+                edits.push(SettingsEdit::Exposure(exposure));
+            }
+            let mut gamma = view.gamma;
+            if ui.add(egui::Slider::new(&mut gamma, 1.0..=3.0).text("gamma")).changed() {
+                edits.push(SettingsEdit::Gamma(gamma));
+            }
+            if let Some(mut reflectivity) = view.reflectivity {
+                if ui.add(egui::Slider::new(&mut reflectivity, 0.0..=1.0).text("reflectivity")).changed() {
+                    edits.push(SettingsEdit::Reflectivity(reflectivity));
+                }
+            }
+            for (label, color, cursor_in) in [
+                ("clear color (cursor in)", view.clear_color_cursor_in, true),
+                ("clear color (cursor out)", view.clear_color_cursor_out, false),
+            ] {
+                let mut rgb = [color.r as f32, color.g as f32, color.b as f32];
+                ui.horizontal(|ui| {
+                    if ui.color_edit_button_rgb(&mut rgb).changed() {
+                        edits.push(SettingsEdit::ClearColor { cursor_in, rgb });
+                    }
+                    ui.label(label);
+                });
+            }
+        });
+
+        let texture_count = view.texture_layers as i32;
+        let sampler_names = view.samplers.iter().map(|sampler| sampler.name.as_str()).collect::<Vec<_>>();
+        egui::CollapsingHeader::new(format!("Instances ({})", view.instances.len())).show(ui, |ui| {
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for (index, instance) in view.instances.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("#{}", index));
+                        let mut position: [f32; 3] = instance.position.into();
+                        let mut moved = false;
+                        for coordinate in &mut position {
+                            moved |= ui.add(egui::DragValue::new(coordinate).speed(0.05)).changed();
+                        }
+                        if moved {
+                            edits.push(SettingsEdit::InstancePosition { index, position });
+                        }
+                        let mut texture_index = instance.texture_index;
+                        if ui.add(
+                            egui::DragValue::new(&mut texture_index).clamp_range(0..=texture_count - 1).prefix("texture ")
+                        ).changed() {
+                            edits.push(SettingsEdit::InstanceTexture { index, texture_index });
+                        }
+                        let mut sampler_index = instance.sampler_index;
+                        let selected = sampler_names.get(sampler_index as usize).copied().unwrap_or_default();
+                        egui::ComboBox::from_id_source(("sampler", index)).selected_text(selected).show_ui(ui, |ui| {
+                            for (option, name) in sampler_names.iter().enumerate() {
+                                if ui.selectable_value(&mut sampler_index, option as i32, *name).changed() {
+                                    edits.push(SettingsEdit::InstanceSampler { index, sampler_index });
+                                }
+                            }
+                        });
+                    });
+                }
+            });
+        });
+    });
+}
@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use winit::event::VirtualKeyCode;
+
+use wgpu_sandbox::error::RendererError;
+use wgpu_sandbox::input::{InputEvent, InputSource};
+use wgpu_sandbox::options::Options;
+use wgpu_sandbox::replay::{RecordedEvent, Recording, ReplayEvent};
+use wgpu_sandbox::scene::Scene;
+use wgpu_sandbox::{render_headless, CameraPose};
+
+const DEFAULT_SCENE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/default.ron");
+
+fn key(tick: u64, key: VirtualKeyCode, pressed: bool) -> RecordedEvent {
+    RecordedEvent {
+        tick,
+        time: tick as f32 / Recording::tick_rate() as f32,
+        event: ReplayEvent::Input(InputEvent::Button {
+            source: InputSource::Key(key),
+            pressed,
+        }),
+    }
+}
+
+fn replay(scene: &Scene, recording: &Path) -> Result<Vec<CameraPose>, RendererError> {
+    let options = Options::parse_from([
+        "wgpu_sandbox".as_ref(),
+        "--replay".as_ref(),
+        recording.as_os_str(),
+        "--width".as_ref(),
+        "64".as_ref(),
+        "--height".as_ref(),
+        "64".as_ref(),
+    ]);
+    Ok(pollster::block_on(render_headless(scene, &options))?.camera_path)
+}
+
+#[test]
+fn replay_produces_identical_camera_path() {
+    let recording = Recording {
+        tick_rate: Recording::tick_rate(),
+        ticks: 40,
+        events: vec![
+            key(2, VirtualKeyCode::W, true),
+            key(10, VirtualKeyCode::A, true),
+            key(15, VirtualKeyCode::W, false),
+            key(20, VirtualKeyCode::A, false),
+            key(20, VirtualKeyCode::D, true),
+            key(30, VirtualKeyCode::D, false),
+            key(32, VirtualKeyCode::S, true),
+            key(38, VirtualKeyCode::S, false),
+        ],
+    };
+    let path: PathBuf = Path::new(env!("CARGO_TARGET_TMPDIR")).join("replay_determinism.ron");
+    recording.save(&path).unwrap();
+    assert_eq!(Recording::load(&path).unwrap(), recording);
+
+    let scene = Scene::load(Path::new(DEFAULT_SCENE_PATH)).unwrap();
+    let first = match replay(&scene, &path) {
+        Err(RendererError::NoAdapter { .. }) => {
+            eprintln!("no adapter available, skipping headless replay");
+            return;
+        }
+        result => result.unwrap(),
+    };
+    let second = replay(&scene, &path).unwrap();
+
+    assert_eq!(first.len(), 40);
+    assert_eq!(first, second);
+    // Recorded input has to actually move the camera.
+    assert_ne!(first[1], first[14]);
+    assert_ne!(first[14], first[29]);
+}
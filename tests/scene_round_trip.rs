@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use wgpu_sandbox::scene::{InstanceDescription, InstanceSource, Scene, SceneDescription, TextureDescription};
+use wgpu_sandbox::texture_format::TextureFormat;
+
+const DEFAULT_SCENE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/default.ron");
+
+#[test]
+fn load_save_load_produces_identical_scene() {
+    let path = Path::new(DEFAULT_SCENE_PATH);
+    let scene = Scene::load(path).unwrap();
+    let camera = scene.description.build_camera(16.0 / 9.0);
+    let instances = scene.description.build_instances();
+
+    let saved = scene.description.with_runtime_state(&camera, scene.description.camera.speed, &instances);
+    let source = saved.to_ron_string().unwrap();
+    let reloaded = SceneDescription::parse(&source, path).unwrap();
+    reloaded.validate(&source, path, scene.texture_layers).unwrap();
+
+    assert_eq!(saved, reloaded);
+    assert_eq!(instances, reloaded.build_instances());
+    assert_eq!(scene.description.camera, reloaded.camera);
+    assert_eq!(scene.description.textures, reloaded.textures);
+    assert_eq!(scene.description.clear_color, reloaded.clear_color);
+    assert_eq!(scene.description.renderer, reloaded.renderer);
+
+    // Saving already expanded scene once more must not change anything.
+    let saved_again = reloaded.with_runtime_state(&reloaded.build_camera(16.0 / 9.0), reloaded.camera.speed, &reloaded.build_instances());
+    assert_eq!(reloaded, saved_again);
+}
+
+#[test]
+fn textures_are_written_with_or_without_format() {
+    let textures: Vec<TextureDescription> = ron::de::from_str(r#"["grass.png", ("height.png", R16Unorm)]"#).unwrap();
+    assert_eq!(textures, vec![
+        TextureDescription::Path("grass.png".into()),
+        TextureDescription::WithFormat("height.png".into(), TextureFormat::R16Unorm),
+    ]);
+    assert_eq!(textures[0].format(), None);
+    assert_eq!(textures[1].path(), "height.png");
+
+    let path = Path::new(DEFAULT_SCENE_PATH);
+    let mut description = Scene::load(path).unwrap().description;
+    description.textures = textures;
+    let source = description.to_ron_string().unwrap();
+    assert_eq!(SceneDescription::parse(&source, path).unwrap(), description);
+}
+
+#[test]
+fn scenes_with_linear_sampler_flags_still_load() {
+    let path = Path::new(DEFAULT_SCENE_PATH);
+    let source = std::fs::read_to_string(path).unwrap();
+    let old_source = source.replace("row_samplers: [1, 0]", "row_linear_sampler: [true, false]");
+    assert_ne!(source, old_source);
+    assert_eq!(SceneDescription::parse(&old_source, path).unwrap(), SceneDescription::parse(&source, path).unwrap());
+
+    let instance: InstanceDescription = ron::de::from_str("(position: (0.0, 0.0, 0.0), texture_index: 0, use_linear_sampler: true)").unwrap();
+    assert_eq!(instance.sampler, 1);
+    let instance: InstanceDescription = ron::de::from_str("(position: (0.0, 0.0, 0.0), texture_index: 0, sampler: 2)").unwrap();
+    assert_eq!(instance.sampler, 2);
+
+    // Saved scenes use the new names.
+    let description = SceneDescription::parse(&old_source, path).unwrap();
+    let saved = description.to_ron_string().unwrap();
+    assert!(saved.contains("row_samplers") && !saved.contains("linear_sampler"));
+    assert!(description.instances.iter().any(|source| matches!(source, InstanceSource::Grid(grid) if grid.row_samplers == [1, 0])));
+}
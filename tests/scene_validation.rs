@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use wgpu_sandbox::scene::{Scene, SceneDescription, SceneError};
+
+const DEFAULT_SCENE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/default.ron");
+
+// Default scene with given animation on its grid, validated as if it had 4 texture layers.
+fn validate_animation(animation: &str) -> Result<(), SceneError> {
+    let path = Path::new(DEFAULT_SCENE_PATH);
+    let source = std::fs::read_to_string(path).unwrap()
+        .replace("row_samplers: [1, 0],", &format!("row_samplers: [1, 0], animation: Some({}),", animation));
+    let description = SceneDescription::parse(&source, path).unwrap();
+    description.validate(&source, path, 4)
+}
+
+fn invalid_field(result: Result<(), SceneError>) -> String {
+    match result {
+        Err(SceneError::Invalid { field, .. }) => field,
+        other => panic!("expected invalid field, got {:?}", other),
+    }
+}
+
+#[test]
+fn animation_frames_have_to_be_texture_layers() {
+    validate_animation("(frame_count: 2, frames_per_second: 4.0)").unwrap();
+    assert_eq!(invalid_field(validate_animation("(frame_count: 0, frames_per_second: 4.0)")), "instances[0].animation.frame_count");
+    assert_eq!(invalid_field(validate_animation("(frame_count: 5, frames_per_second: 4.0)")), "instances[0].animation.frame_count");
+    // Used to overflow when added to the first texture.
+    assert_eq!(invalid_field(validate_animation("(frame_count: 4294967295, frames_per_second: 4.0)")), "instances[0].animation.frame_count");
+    assert_eq!(
+        invalid_field(validate_animation("(frame_count: 2, frames_per_second: 4.0, sheet_columns: Some(4294967295))")),
+        "instances[0].animation.sheet_columns",
+    );
+}
+
+#[test]
+fn bundled_scenes_are_valid() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+    for entry in std::fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "ron") {
+            if let Err(error) = Scene::load(&path) {
+                panic!("{}: {}", path.display(), error);
+            }
+        }
+    }
+}
+
+#[test]
+fn zero_quaternion_is_invalid() {
+    let path = Path::new(DEFAULT_SCENE_PATH);
+    let source = std::fs::read_to_string(path).unwrap().replace(
+        "    instances: [\n",
+        "    instances: [\n        Single((position: (0.0, 0.0, 0.0), rotation: Quaternion((0.0, 0.0, 0.0, 0.0)), texture_index: 0)),\n",
+    );
+    let description = SceneDescription::parse(&source, path).unwrap();
+    assert_eq!(invalid_field(description.validate(&source, path, 2)), "instances[0].rotation");
+}
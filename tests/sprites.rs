@@ -0,0 +1,72 @@
+use clap::Parser;
+
+use wgpu_sandbox::options::Options;
+use wgpu_sandbox::render_headless_with_sprites;
+use wgpu_sandbox::scene::Scene;
+use wgpu_sandbox::sprite::SpriteTransform;
+
+const SPRITES_SCENE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/sprites.ron");
+
+#[test]
+fn sprites_can_be_added_every_frame() {
+    let scene = Scene::load(SPRITES_SCENE_PATH.as_ref()).unwrap();
+    assert!(scene.description.instances.is_empty());
+    let options = Options::parse_from(["wgpu_sandbox", "--width", "64", "--height", "64", "--frames", "2"]);
+
+    let mut frames = 0;
+    let output = pollster::block_on(render_headless_with_sprites(&scene, &options, |sprites| {
+        // Red tinted grass covering the whole frame, scene sprites are all below it.
+        sprites.set_sampler(1);
+        sprites.draw_sprite(0, Some([0.0, 0.0, 8.0, 8.0]), &SpriteTransform { scale: [8.0, 8.0], ..SpriteTransform::default() }, [1.0, 0.0, 0.0, 1.0]);
+        frames += 1;
+    })).unwrap();
+
+    assert_eq!(frames, 2);
+    for pixel in output.frame.pixels() {
+        assert!(pixel[0] > 0 && pixel[1] == 0 && pixel[2] == 0, "{:?} is not red", pixel);
+    }
+}
+
+#[test]
+fn sprites_are_drawn_by_layer_then_depth_then_order() {
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+    let mut scene = Scene::load(SPRITES_SCENE_PATH.as_ref()).unwrap();
+    scene.description.sprites.clear();
+    let options = Options::parse_from(["wgpu_sandbox", "--width", "64", "--height", "64"]);
+
+    // Every quarter of the frame has two overlapping sprites, the one expected on top is drawn second there.
+    let quarter = |x: f32, y: f32, layer: i32, depth: f32| SpriteTransform { position: [x, y], scale: [4.0, 4.0], layer, depth, ..SpriteTransform::default() };
+    let output = pollster::block_on(render_headless_with_sprites(&scene, &options, |sprites| {
+        sprites.set_sampler(0);
+        let mut draw = |transform: SpriteTransform, tint| sprites.draw_sprite(0, Some([0.0, 0.0, 8.0, 8.0]), &transform, tint);
+        // Higher layer is on top even when it is added first.
+        draw(quarter(0.0, 0.0, 1, 0.0), GREEN);
+        draw(quarter(0.0, 0.0, 0, 0.0), RED);
+        // Within a layer the nearer sprite is on top.
+        draw(quarter(32.0, 0.0, 0, 0.0), GREEN);
+        draw(quarter(32.0, 0.0, 0, 0.5), RED);
+        // With the same layer and depth the sprite added last is on top.
+        draw(quarter(0.0, 32.0, 2, 0.25), RED);
+        draw(quarter(0.0, 32.0, 2, 0.25), GREEN);
+        draw(quarter(32.0, 32.0, 2, 0.25), GREEN);
+        draw(quarter(32.0, 32.0, 2, 0.25), RED);
+    })).unwrap();
+
+    let is_green = |x, y| {
+        let pixel = output.frame.get_pixel(x, y);
+        match (pixel[0], pixel[1]) {
+            (0, green) if green > 0 => true,
+            (red, 0) if red > 0 => false,
+            _ => panic!("{:?} at ({}, {}) is neither red nor green", pixel, x, y),
+        }
+    };
+    for y in (0..32).step_by(4) {
+        for x in (0..32).step_by(4) {
+            assert!(is_green(x, y));
+            assert!(is_green(x + 32, y));
+            assert!(is_green(x, y + 32));
+            assert!(!is_green(x + 32, y + 32));
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use clap::Parser;
+
+use wgpu_sandbox::error::RendererError;
+use wgpu_sandbox::options::Options;
+use wgpu_sandbox::render_headless_with_sprites;
+use wgpu_sandbox::scene::{Scene, TextureDescription};
+use wgpu_sandbox::sprite::SpriteTransform;
+use wgpu_sandbox::texture_format::TextureFormat;
+
+const SPRITES_SCENE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/sprites.ron");
+
+// Frame with only the given layer of scene texture drawn over all of it, textures have the given formats.
+fn render_layer(formats: [TextureFormat; 2], layer: u32) -> Result<image::RgbaImage, RendererError> {
+    let mut scene = Scene::load(SPRITES_SCENE_PATH.as_ref()).unwrap();
+    scene.description.sprites.clear();
+    for (texture, format) in scene.description.textures.iter_mut().zip(formats) {
+        *texture = TextureDescription::WithFormat(texture.path().to_owned(), format);
+    }
+    let options = Options::parse_from(["wgpu_sandbox", "--width", "64", "--height", "64"]);
+    let output = pollster::block_on(render_headless_with_sprites(&scene, &options, |sprites| {
+        sprites.set_sampler(0);
+        sprites.draw_sprite(layer, Some([0.0, 0.0, 8.0, 8.0]), &SpriteTransform { scale: [8.0, 8.0], ..SpriteTransform::default() }, [1.0; 4]);
+    }))?;
+    Ok(output.frame)
+}
+
+fn assert_close(actual: &image::RgbaImage, expected: &image::RgbaImage) {
+    for (a, e) in actual.pixels().zip(expected.pixels()) {
+        assert!(a.0.iter().zip(e.0).all(|(a, e)| a.abs_diff(e) <= 1), "{:?} is not {:?}", a, e);
+    }
+}
+
+#[test]
+fn mixed_layers_read_the_same_as_layers_of_one_format() {
+    let mixed = match render_layer([TextureFormat::Srgb, TextureFormat::Linear], 1) {
+        Err(RendererError::NoAdapter { .. }) => {
+            eprintln!("no adapter available, skipping headless render");
+            return;
+        }
+        result => result.unwrap(),
+    };
+    // Layers are stored as HDR, the linear one must not be decoded as sRGB colour.
+    assert_close(&mixed, &render_layer([TextureFormat::Linear; 2], 1).unwrap());
+    assert_ne!(mixed, render_layer([TextureFormat::Srgb; 2], 1).unwrap());
+
+    let mixed = render_layer([TextureFormat::Srgb, TextureFormat::Linear], 0).unwrap();
+    assert_close(&mixed, &render_layer([TextureFormat::Srgb; 2], 0).unwrap());
+}
+
+#[test]
+fn r16_unorm_layers_are_not_mixed_with_srgb_ones() {
+    match render_layer([TextureFormat::Srgb, TextureFormat::R16Unorm], 1) {
+        Err(RendererError::NoAdapter { .. }) => eprintln!("no adapter available, skipping headless render"),
+        Err(RendererError::MixedTextureFormats { layer: 1, format: TextureFormat::R16Unorm }) => (),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("R16Unorm layer was mixed with sRGB one"),
+    }
+}